@@ -1,19 +1,15 @@
-use crate::vec::*;
-use rand::prelude::*;
-use rayon::prelude::*;
 use std::fs;
 use std::path::PathBuf;
 
-use crate::config::UserConfig;
-use crate::geom::*;
 use iced::{
     button, scrollable, Align, Application, Button, Column, Command, Container, Element,
     HorizontalAlignment, Image, Length, Row, Scrollable, Space, Text,
 };
 use nfd::Response;
+use prayer::UserConfig;
 use tempfile::NamedTempFile;
 
-use crate::{camera, style};
+use crate::style;
 use names::{Generator, Name};
 use tinyfiledialogs::{MessageBoxIcon, YesNo};
 
@@ -244,46 +240,9 @@ impl Application for AppModel {
 }
 
 async fn trace_main(config: UserConfig) -> Result<Vec<u8>, Error> {
-    let UserConfig { params, scene } = config;
-
-    let w = params.resolution.x;
-    let h = params.resolution.y;
-    let camera = camera::Camera::looking_at(
-        glm::vec3(0.0, 2.0, -5.0),
-        glm::vec3(0.0, 0.0, 0.0),
-        glm::vec3(0.0, 1.0, 0.0),
-        80.0,
-        w as f32 / h as f32,
-    );
-
-    let buffer: Vec<u8> = (0..w * h)
-        .into_par_iter()
-        .flat_map(|i| {
-            let x = i % w;
-            let y = i / w;
-            let color = (0..params.samples)
-                .into_par_iter()
-                .map(|_| {
-                    let mut rng = rand::thread_rng();
-                    let rand: f32 = rng.gen();
-                    let u = (x as f32 + rand) / w as f32;
-                    let rand: f32 = rng.gen();
-                    let v = (y as f32 + rand) / h as f32;
-                    let ray = camera.ray_at(u, v);
-                    trace(&ray, &scene, params.max_light_bounces)
-                })
-                .sum::<Vec3>()
-                / params.samples as f32;
-            let color = glm::vec3(1.0, 1.0, 1.0) - glm::exp(&(-color * params.exposure));
-            vec![
-                (color.x.max(0.0).min(1.0).powf(1.0 / params.gamma) * 255.99) as u8,
-                (color.y.max(0.0).min(1.0).powf(1.0 / params.gamma) * 255.99) as u8,
-                (color.z.max(0.0).min(1.0).powf(1.0 / params.gamma) * 255.99) as u8,
-            ]
-        })
-        .collect::<Vec<_>>();
-
-    Ok(buffer)
+    prayer::render(&config)
+        .map(|img| img.into_raw())
+        .map_err(|_| Error::TraceError)
 }
 
 fn button<'a, Message>(state: &'a mut button::State, label: &str) -> Button<'a, Message> {
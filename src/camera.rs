@@ -1,42 +1,324 @@
 use nalgebra_glm as glm;
+use rand::prelude::*;
+use serde::Deserialize;
 
-use crate::ray::Ray;
+use crate::ray::{Differentials, Ray};
 use crate::Vec3;
 
 use std::f32::consts::PI;
 
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Equirectangular,
+    Fisheye,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub projection: Projection,
+    pub position: Vec3,
+    #[serde(alias = "target")]
+    pub looking_at: Vec3,
+    pub up: Vec3,
+    pub fov: f32,
+    pub aspect: Option<f32>,
+    pub aperture: f32,
+    pub focus_distance: Option<f32>,
+    /// Height of the view in world units for orthographic cameras
+    pub ortho_height: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            projection: Projection::Perspective,
+            position: Vec3::new(0.0, 2.0, -5.0),
+            looking_at: glm::zero(),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            fov: 80.0,
+            aspect: None,
+            aperture: 0.0,
+            focus_distance: None,
+            ortho_height: 2.0,
+        }
+    }
+}
+
+impl CameraConfig {
+    pub fn build(&self, resolution: glm::UVec2) -> Camera {
+        let aspect = self
+            .aspect
+            .unwrap_or(resolution.x as f32 / resolution.y as f32);
+        let (position, at, up) = (self.position, self.looking_at, self.up);
+        match self.projection {
+            Projection::Perspective => {
+                let focus_distance = self
+                    .focus_distance
+                    .unwrap_or_else(|| glm::distance(&position, &at));
+                Camera::thin_lens(
+                    position,
+                    at,
+                    up,
+                    self.fov,
+                    aspect,
+                    self.aperture,
+                    focus_distance,
+                )
+            }
+            Projection::Orthographic => {
+                Camera::orthographic(position, at, up, self.ortho_height, aspect)
+            }
+            Projection::Equirectangular => Camera::equirectangular(position, at, up),
+            Projection::Fisheye => Camera::fisheye(position, at, up, self.fov, aspect),
+        }
+        .with_resolution(resolution)
+    }
+}
+
 pub struct Camera {
+    projection: Projection,
     position: Vec3,
     bl_corner: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    lens_radius: f32,
+    fov: f32,
+    aspect: f32,
+    /// Size of a pixel in image coordinates, zero if the resolution is unknown
+    pixel: glm::Vec2,
+}
+
+/// Orthonormal camera frame: `u` points right and `v` down in image space, `w` points backwards
+fn basis(position: Vec3, at: Vec3, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = glm::normalize(&(position - at));
+    let u: Vec3 = glm::normalize(&w.cross(&up));
+    let v = w.cross(&u);
+    (u, v, w)
 }
 
 impl Camera {
     pub fn looking_at(position: Vec3, at: Vec3, up: Vec3, fov: f32, aspect: f32) -> Self {
+        Self::thin_lens(position, at, up, fov, aspect, 0.0, 1.0)
+    }
+
+    /// Camera with a circular lens of radius `aperture`, in focus at `focus_distance`
+    pub fn thin_lens(
+        position: Vec3,
+        at: Vec3,
+        up: Vec3,
+        fov: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_distance: f32,
+    ) -> Self {
         let theta = fov * PI / 180.0;
         let half_h = f32::tan(theta / 2.0);
         let half_w = aspect * half_h;
 
-        let w = glm::normalize(&(position - at));
-        let u: Vec3 = glm::normalize(&w.cross(&up));
-        let v = w.cross(&u);
-
-        let bl_corner = position - half_w * u - half_h * v - w;
-        let horizontal = 2.0 * half_w * u;
-        let vertical = 2.0 * half_h * v;
+        let (u, v, w) = basis(position, at, up);
+        let bl_corner = position - focus_distance * (half_w * u + half_h * v + w);
+        let horizontal = 2.0 * half_w * focus_distance * u;
+        let vertical = 2.0 * half_h * focus_distance * v;
         Camera {
+            projection: Projection::Perspective,
             position,
             bl_corner,
             horizontal,
             vertical,
+            u,
+            v,
+            w,
+            lens_radius: aperture,
+            fov: theta,
+            aspect,
+            pixel: glm::zero(),
+        }
+    }
+
+    /// Parallel projection covering `height` world units vertically
+    pub fn orthographic(position: Vec3, at: Vec3, up: Vec3, height: f32, aspect: f32) -> Self {
+        let camera = Self::looking_at(position, at, up, 90.0, aspect);
+        let horizontal = height * aspect * camera.u;
+        let vertical = height * camera.v;
+        Camera {
+            projection: Projection::Orthographic,
+            bl_corner: position - 0.5 * (horizontal + vertical),
+            horizontal,
+            vertical,
+            ..camera
         }
     }
 
-    pub fn ray_at(&self, x: f32, y: f32) -> Ray {
-        Ray::new(
-            self.position,
-            self.bl_corner + x * self.horizontal + y * self.vertical - self.position,
-        )
+    /// Full 360 by 180 degree latitude-longitude panorama centered on `at`
+    pub fn equirectangular(position: Vec3, at: Vec3, up: Vec3) -> Self {
+        Camera {
+            projection: Projection::Equirectangular,
+            ..Self::looking_at(position, at, up, 90.0, 2.0)
+        }
+    }
+
+    /// Equidistant fisheye, `fov` is the angle across the image circle
+    pub fn fisheye(position: Vec3, at: Vec3, up: Vec3, fov: f32, aspect: f32) -> Self {
+        Camera {
+            projection: Projection::Fisheye,
+            ..Self::looking_at(position, at, up, fov, aspect)
+        }
+    }
+
+    /// Enables ray differentials for images of the given size
+    pub fn with_resolution(self, resolution: glm::UVec2) -> Self {
+        Camera {
+            pixel: glm::vec2(1.0 / resolution.x as f32, 1.0 / resolution.y as f32),
+            ..self
+        }
+    }
+
+    /// Returns `None` for image points not covered by the projection
+    pub fn ray_at(&self, x: f32, y: f32) -> Option<Ray> {
+        // Differentials go through the same point on the lens
+        let lens = self.lens_offset();
+        let ray = self.project(x, y, &lens)?;
+        if self.pixel == glm::zero::<glm::Vec2>() {
+            return Some(ray);
+        }
+        match (
+            self.project(x + self.pixel.x, y, &lens),
+            self.project(x, y + self.pixel.y, &lens),
+        ) {
+            (Some(rx), Some(ry)) => Some(ray.with_differentials(Differentials {
+                rx_origin: rx.origin,
+                rx_direction: rx.direction,
+                ry_origin: ry.origin,
+                ry_direction: ry.direction,
+            })),
+            _ => Some(ray),
+        }
+    }
+
+    fn project(&self, x: f32, y: f32, lens: &Vec3) -> Option<Ray> {
+        let forward = -self.w;
+        match self.projection {
+            Projection::Perspective => {
+                let origin = self.position + lens;
+                let direction = self.bl_corner + x * self.horizontal + y * self.vertical - origin;
+                Some(Ray::new(origin, direction))
+            }
+            Projection::Orthographic => {
+                let origin = self.bl_corner + x * self.horizontal + y * self.vertical;
+                Some(Ray::new(origin, forward))
+            }
+            Projection::Equirectangular => {
+                let phi = (x - 0.5) * 2.0 * PI;
+                let theta = (y - 0.5) * PI;
+                let horizontal = f32::sin(phi) * self.u + f32::cos(phi) * forward;
+                let direction = f32::cos(theta) * horizontal + f32::sin(theta) * self.v;
+                Some(Ray::new(self.position, direction))
+            }
+            Projection::Fisheye => {
+                let px = (2.0 * x - 1.0) * self.aspect;
+                let py = 2.0 * y - 1.0;
+                let r = f32::sqrt(px * px + py * py);
+                if r > 1.0 {
+                    return None;
+                }
+                let angle = r * self.fov / 2.0;
+                let radial = if r > 0.0 {
+                    (px * self.u + py * self.v) / r
+                } else {
+                    glm::zero()
+                };
+                let direction = f32::sin(angle) * radial + f32::cos(angle) * forward;
+                Some(Ray::new(self.position, direction))
+            }
+        }
+    }
+
+    fn lens_offset(&self) -> Vec3 {
+        if self.lens_radius <= 0.0 {
+            return glm::zero();
+        }
+        let mut rng = rand::thread_rng();
+        let r = self.lens_radius * f32::sqrt(rng.gen::<f32>());
+        let phi = rng.gen::<f32>() * 2.0 * PI;
+        r * (f32::cos(phi) * self.u + f32::sin(phi) * self.v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Camera at (0, 1, 5) looking down -z, so image right is -x and image up is +y
+    fn camera(projection: Projection, fov: f32) -> Camera {
+        CameraConfig {
+            projection,
+            position: Vec3::new(0.0, 1.0, 5.0),
+            looking_at: Vec3::new(0.0, 1.0, 0.0),
+            fov,
+            ..CameraConfig::default()
+        }
+        .build(glm::vec2(100, 100))
+    }
+
+    fn assert_direction(ray: &Ray, expected: Vec3) {
+        let direction = glm::normalize(&ray.direction);
+        let expected = glm::normalize(&expected);
+        assert!(
+            glm::distance(&direction, &expected) < 1e-5,
+            "{:?} is not {:?}",
+            direction,
+            expected
+        );
+    }
+
+    #[test]
+    fn center_looks_forward() {
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        for &projection in &[
+            Projection::Perspective,
+            Projection::Orthographic,
+            Projection::Equirectangular,
+            Projection::Fisheye,
+        ] {
+            let ray = camera(projection, 90.0).ray_at(0.5, 0.5).unwrap();
+            assert_direction(&ray, forward);
+            assert!(glm::distance(&ray.origin, &Vec3::new(0.0, 1.0, 5.0)) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn frame_edges() {
+        let right = Vec3::new(-1.0, 0.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+
+        // Half of a 90 degree field of view
+        let perspective = camera(Projection::Perspective, 90.0);
+        assert_direction(&perspective.ray_at(1.0, 0.5).unwrap(), right + forward);
+        assert_direction(&perspective.ray_at(0.5, 0.0).unwrap(), up + forward);
+
+        let orthographic = camera(Projection::Orthographic, 90.0)
+            .ray_at(1.0, 0.0)
+            .unwrap();
+        assert_direction(&orthographic, forward);
+        assert!(glm::distance(&orthographic.origin, &Vec3::new(-1.0, 2.0, 5.0)) < 1e-5);
+
+        let equirectangular = camera(Projection::Equirectangular, 90.0);
+        assert_direction(&equirectangular.ray_at(0.75, 0.5).unwrap(), right);
+        assert_direction(&equirectangular.ray_at(0.0, 0.5).unwrap(), -forward);
+        assert_direction(&equirectangular.ray_at(0.5, 0.0).unwrap(), up);
+
+        // The image circle of a 180 degree fisheye touches the edges sideways
+        let fisheye = camera(Projection::Fisheye, 180.0);
+        assert_direction(&fisheye.ray_at(1.0, 0.5).unwrap(), right);
+        assert_direction(&fisheye.ray_at(0.5, 0.0).unwrap(), up);
+        assert!(fisheye.ray_at(1.0, 1.0).is_none());
     }
 }
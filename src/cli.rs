@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use nalgebra_glm::UVec2;
+
+use prayer::UserConfig;
+
+pub const USAGE: &str = "\
+Usage: prayer [CONFIG] [OPTIONS]
+
+Without arguments the graphical interface is started.
+
+Options:
+    -o, --output <FILE>         Output image path (default: CONFIG with .png extension)
+    -s, --samples <N>           Override the number of samples per pixel
+    -r, --resolution <WxH>      Override the output resolution
+    -c, --camera <NAME>         Render from the named camera in [cameras]
+    -t, --threads <N>           Number of render threads (default: all cores)
+    -h, --help                  Print this message";
+
+pub struct Options {
+    pub config: PathBuf,
+    pub output: PathBuf,
+    pub samples: Option<usize>,
+    pub resolution: Option<UVec2>,
+    pub camera: Option<String>,
+    pub threads: Option<usize>,
+}
+
+impl Options {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut config = None;
+        let mut output = None;
+        let mut samples = None;
+        let mut resolution = None;
+        let mut camera = None;
+        let mut threads = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for '{}'", arg))
+            };
+            match arg.as_str() {
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                "-s" | "--samples" => samples = Some(parse_number(&value()?)?),
+                "-r" | "--resolution" => resolution = Some(parse_resolution(&value()?)?),
+                "-c" | "--camera" => camera = Some(value()?),
+                "-t" | "--threads" => threads = Some(parse_number(&value()?)?),
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unknown option '{}'\n\n{}", arg, USAGE))
+                }
+                _ if config.is_none() => config = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument '{}'\n\n{}", arg, USAGE)),
+            }
+        }
+
+        let config = config.ok_or_else(|| format!("No config file given\n\n{}", USAGE))?;
+        let output = output.unwrap_or_else(|| config.with_extension("png"));
+        Ok(Options {
+            config,
+            output,
+            samples,
+            resolution,
+            camera,
+            threads,
+        })
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Expected a positive number, got '{}'", s)),
+    }
+}
+
+fn parse_resolution(s: &str) -> Result<UVec2, String> {
+    let error = || format!("Expected a resolution like 800x600, got '{}'", s);
+    let mut dims = s.split('x').map(str::parse::<u32>);
+    match (dims.next(), dims.next(), dims.next()) {
+        (Some(Ok(w)), Some(Ok(h)), None) if w > 0 && h > 0 => Ok(UVec2::new(w, h)),
+        _ => Err(error()),
+    }
+}
+
+pub fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+
+    let mut config = UserConfig::from_file(&options.config)
+        .map_err(|e| format!("Error in {}: {}", options.config.display(), e))?;
+    if let Some(samples) = options.samples {
+        config.params.samples = samples;
+    }
+    if let Some(resolution) = options.resolution {
+        config.params.resolution = resolution;
+    }
+    if let Some(camera) = options.camera {
+        config.params.camera = Some(camera);
+    }
+
+    prayer::render(&config)?
+        .save(&options.output)
+        .map_err(|e| format!("Image could not be saved: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn defaults_from_the_config_path() {
+        let options = parse(&["scenes/box.toml"]).unwrap();
+        assert_eq!(options.config, PathBuf::from("scenes/box.toml"));
+        assert_eq!(options.output, PathBuf::from("scenes/box.png"));
+        assert_eq!(options.samples, None);
+        assert_eq!(options.resolution, None);
+        assert_eq!(options.camera, None);
+        assert_eq!(options.threads, None);
+    }
+
+    #[test]
+    fn flags_in_any_order() {
+        let options = parse(&[
+            "-s",
+            "64",
+            "--resolution",
+            "320x240",
+            "box.toml",
+            "--camera",
+            "top",
+            "-o",
+            "out/box.png",
+            "--threads",
+            "2",
+        ])
+        .unwrap();
+        assert_eq!(options.config, PathBuf::from("box.toml"));
+        assert_eq!(options.output, PathBuf::from("out/box.png"));
+        assert_eq!(options.samples, Some(64));
+        assert_eq!(options.resolution, Some(UVec2::new(320, 240)));
+        assert_eq!(options.camera.as_deref(), Some("top"));
+        assert_eq!(options.threads, Some(2));
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        for args in &[
+            &[][..],
+            &["box.toml", "--frobnicate"],
+            &["box.toml", "other.toml"],
+            &["box.toml", "-s"],
+            &["box.toml", "-s", "0"],
+            &["box.toml", "-s", "many"],
+            &["box.toml", "-t", "-1"],
+            &["box.toml", "-r", "800"],
+            &["box.toml", "-r", "800x0"],
+            &["box.toml", "-r", "1x2x3"],
+        ] {
+            assert!(parse(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+}
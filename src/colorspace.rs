@@ -0,0 +1,149 @@
+use serde::Deserialize;
+
+use crate::Vec3;
+use nalgebra_glm as glm;
+
+/// Encoding of the values stored in a texture file
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// sRGB transfer curve and primaries, as used by most 8-bit images
+    Srgb,
+    /// Linear values with sRGB primaries, as used by HDR and EXR files
+    Linear,
+    /// Data such as normals, read as-is and never converted
+    Raw,
+}
+
+impl ColorSpace {
+    /// Linear value of a stored channel value
+    pub fn decode(self, c: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb => {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            ColorSpace::Linear | ColorSpace::Raw => c,
+        }
+    }
+}
+
+/// Curve the rendered image is encoded with
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transfer {
+    #[default]
+    Srgb,
+    Rec709,
+    Linear,
+    /// Pure power curve, only set through the deprecated `gamma` parameter
+    #[serde(skip)]
+    Gamma(f32),
+}
+
+impl Transfer {
+    /// Encoded value of a linear value between 0 and 1
+    pub fn encode(self, c: f32) -> f32 {
+        match self {
+            Transfer::Srgb => {
+                if c <= 0.003_130_8 {
+                    c * 12.92
+                } else {
+                    1.055 * c.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Transfer::Rec709 => {
+                if c < 0.018 {
+                    c * 4.5
+                } else {
+                    1.099 * c.powf(0.45) - 0.099
+                }
+            }
+            Transfer::Linear => c,
+            Transfer::Gamma(gamma) => c.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// Primaries lighting is computed in. Colors in the scene are always given with sRGB/Rec. 709
+/// primaries and are converted after loading.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkingSpace {
+    #[default]
+    Rec709,
+    /// ACES AP1 primaries, which give more plausible results for saturated colors
+    AcesCg,
+}
+
+impl WorkingSpace {
+    /// Matrix taking Rec. 709 colors into this space, if it differs
+    pub fn from_rec709(self) -> Option<glm::Mat3> {
+        match self {
+            WorkingSpace::Rec709 => None,
+            WorkingSpace::AcesCg => Some(glm::mat3(
+                0.613_097, 0.339_523, 0.047_379, //
+                0.070_194, 0.916_354, 0.013_452, //
+                0.020_616, 0.109_570, 0.869_815,
+            )),
+        }
+    }
+
+    /// Rec. 709 color of a color in this space, for display
+    pub fn to_rec709(self, color: Vec3) -> Vec3 {
+        match self {
+            WorkingSpace::Rec709 => color,
+            WorkingSpace::AcesCg => {
+                glm::mat3(
+                    1.704_859, -0.621_715, -0.083_299, //
+                    -0.130_078, 1.140_734, -0.010_560, //
+                    -0.023_964, -0.128_975, 1.153_013,
+                ) * color
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip() {
+        for i in 0..=100 {
+            let c = i as f32 / 100.0;
+            let round_trip = Transfer::Srgb.encode(ColorSpace::Srgb.decode(c));
+            assert!((round_trip - c).abs() < 1e-5, "{} {}", c, round_trip);
+        }
+    }
+
+    #[test]
+    fn curves_are_continuous() {
+        let jump = |f: &dyn Fn(f32) -> f32, x: f32| (f(x * 1.0001) - f(x * 0.9999)).abs();
+        assert!(jump(&|c| ColorSpace::Srgb.decode(c), 0.04045) < 1e-5);
+        assert!(jump(&|c| Transfer::Srgb.encode(c), 0.003_130_8) < 1e-4);
+        assert!(jump(&|c| Transfer::Rec709.encode(c), 0.018) < 1e-3);
+    }
+
+    #[test]
+    fn working_space_round_trip() {
+        let matrix = WorkingSpace::AcesCg.from_rec709().unwrap();
+        let colors = [
+            glm::vec3(1.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            glm::vec3(0.0, 0.0, 1.0),
+            glm::vec3(0.2, 0.5, 0.9),
+        ];
+        // Both matrices are rounded to six decimals
+        for color in colors.iter() {
+            let round_trip = WorkingSpace::AcesCg.to_rec709(matrix * color);
+            assert!(glm::distance(&round_trip, color) < 1e-3, "{}", round_trip);
+        }
+        // White stays white
+        let white = matrix * glm::vec3(1.0, 1.0, 1.0);
+        assert!(glm::distance(&white, &glm::vec3(1.0, 1.0, 1.0)) < 1e-3);
+    }
+}
@@ -1,11 +1,16 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
 use nalgebra_glm::{zero, UVec2};
 use serde::Deserialize;
 
+use crate::camera::CameraConfig;
+use crate::colorspace::{Transfer, WorkingSpace};
 use crate::geom::Scene;
+use crate::integrator::IntegratorType;
 use crate::Vec3;
 
 #[derive(Deserialize, Clone)]
@@ -13,12 +18,27 @@ use crate::Vec3;
 pub struct RenderParams {
     pub resolution: UVec2,
     pub samples: usize,
-    pub max_light_bounces: usize,
-    pub gamma: f32,
+    pub integrator: IntegratorType,
+    /// Hard cap on path length, unbounded if not set
+    pub max_light_bounces: Option<usize>,
+    /// Path length after which paths are terminated by russian roulette
+    pub roulette_depth: usize,
+    /// Occlusion radius of the ambient occlusion integrator
+    pub ao_distance: f32,
+    /// Distance shown as white by the depth integrator
+    pub depth_range: f32,
+    /// Primaries lighting is computed in
+    pub working_space: WorkingSpace,
+    /// Encoding of the output image
+    pub transfer: Transfer,
+    /// Deprecated in favour of `transfer`. Encodes the output with a pure power curve
+    /// instead, as before `transfer` existed.
+    pub gamma: Option<f32>,
     pub exposure: f32,
     pub camera_pos: Vec3,
     pub looking_at: Vec3,
     pub fov: f32,
+    pub camera: Option<String>,
 }
 
 impl Default for RenderParams {
@@ -26,12 +46,19 @@ impl Default for RenderParams {
         RenderParams {
             resolution: UVec2::new(500, 500),
             samples: 10,
-            max_light_bounces: 5,
-            gamma: 2.2,
+            integrator: IntegratorType::default(),
+            max_light_bounces: None,
+            roulette_depth: 3,
+            ao_distance: 1.0,
+            depth_range: 10.0,
+            working_space: WorkingSpace::default(),
+            transfer: Transfer::default(),
+            gamma: None,
             exposure: 1.0,
-            camera_pos: Vec3::new(0.0, 0.0, -1.0),
+            camera_pos: Vec3::new(0.0, 2.0, -5.0),
             looking_at: zero(),
             fov: 80.0,
+            camera: None,
         }
     }
 }
@@ -40,12 +67,50 @@ impl Default for RenderParams {
 pub struct UserConfig {
     pub params: RenderParams,
     pub scene: Scene,
+    #[serde(default)]
+    pub camera: Option<CameraConfig>,
+    #[serde(default)]
+    pub cameras: HashMap<String, CameraConfig>,
 }
 
 impl UserConfig {
+    /// The camera selected by `params.camera`, falling back to the `[camera]` table
+    /// and then to the camera settings in `[params]`.
+    pub fn camera(&self) -> Result<CameraConfig, String> {
+        match &self.params.camera {
+            Some(name) => self
+                .cameras
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("No camera named '{}' in [cameras]", name)),
+            None => Ok(self.camera.clone().unwrap_or_else(|| CameraConfig {
+                position: self.params.camera_pos,
+                looking_at: self.params.looking_at,
+                fov: self.params.fov,
+                ..CameraConfig::default()
+            })),
+        }
+    }
+
     pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error + '_>> {
         let contents = fs::read_to_string(path)?;
-        let cfg = toml::from_str(&contents)?;
+        let cfg = contents.parse()?;
+        Ok(cfg)
+    }
+}
+
+impl FromStr for UserConfig {
+    type Err = toml::de::Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let mut cfg: UserConfig = toml::from_str(contents)?;
+        cfg.camera().map_err(serde::de::Error::custom)?;
+        if let Some(gamma) = cfg.params.gamma {
+            cfg.params.transfer = Transfer::Gamma(gamma);
+        }
+        if let Some(matrix) = cfg.params.working_space.from_rec709() {
+            cfg.scene.transform_colors(&matrix);
+        }
         Ok(cfg)
     }
 }
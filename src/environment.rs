@@ -1,7 +1,518 @@
+mod cubemap;
+mod distribution;
+mod sky;
+
+use std::fmt;
+
+use rand::prelude::*;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use toml::value::{Table, Value};
+
+pub use cubemap::*;
+pub use distribution::*;
+pub use sky::*;
+
+use crate::geom::Sphere;
+use crate::texture::{ColorTexture, TexCoord, Texture as _};
+use crate::vec::transform_to_world;
+use crate::{Vec2, Vec3};
+use nalgebra_glm as glm;
+
+/// Radiance arriving from infinitely far away, e.g. `[0.5, 0.5, 0.7]`, `"sky.hdr"`,
+/// `{ sky = { sun_direction = [1, 0.5, 0] } }`, `{ cubemap = "cross.hdr" }` or
+/// `{ image = "sky.hdr", rotation = 90, intensity = 2, background = [0, 0, 0] }`
+#[derive(Clone)]
+pub struct Environment {
+    pub map: EnvironmentMap,
+    /// Rotation about the up axis, in degrees
+    pub rotation: f32,
+    pub intensity: f32,
+    /// Seen by camera rays instead of the environment, which then only lights the scene
+    pub background: Option<Box<Environment>>,
+    /// Brightness of the map over its uvs, for maps that can be importance sampled
+    distribution: Option<Distribution2D>,
+    /// Axis and cosine of the half-angle of the sun disk of sky maps, sampled separately
+    sun: Option<(Vec3, f32)>,
+}
+
+/// Fraction of environment samples aimed at the sun when there is one
+const SUN_PROBABILITY: f32 = 0.5;
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvironmentMap {
+    Constant(Vec3),
+    /// Blend from `horizon` up to `zenith`, and down to `ground`
+    Gradient {
+        zenith: Vec3,
+        horizon: Vec3,
+        ground: Option<Vec3>,
+    },
+    /// Latitude-longitude texture
+    Image(ColorTexture),
+    Cubemap(Cubemap),
+    /// Light probe in Debevec's angular map format, with +z at the center and -z around the
+    /// edge
+    Angular(ColorTexture),
+    /// Photograph of a mirrored ball taken looking along +z
+    #[serde(alias = "mirror_ball")]
+    MirrorBall(ColorTexture),
+    Sky(Sky),
+}
+
+/// Environment given as a table keyed by the kind of map
+#[derive(Deserialize)]
+struct EnvironmentTable {
+    #[serde(flatten)]
+    map: EnvironmentMap,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+    background: Option<Box<Environment>>,
+}
+
+/// Keys of `EnvironmentMap`, telling its tables apart from those of textures
+const MAP_KINDS: [&str; 8] = [
+    "constant",
+    "gradient",
+    "image",
+    "cubemap",
+    "angular",
+    "mirrorball",
+    "mirror_ball",
+    "sky",
+];
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl From<ColorTexture> for Environment {
+    fn from(texture: ColorTexture) -> Self {
+        match texture {
+            ColorTexture::Solid(color) => Environment::new(EnvironmentMap::Constant(color)),
+            texture => Environment::new(EnvironmentMap::Image(texture)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Environment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(EnvironmentVisitor)
+    }
+}
+
+struct EnvironmentVisitor;
+
+impl<'de> Visitor<'de> for EnvironmentVisitor {
+    type Value = Environment;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("color, path to latitude-longitude image, or environment table")
+    }
+
+    // Latitude-longitude image file
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        ColorTexture::deserialize(value.into_deserializer()).map(Environment::from)
+    }
+
+    // Constant color
+    fn visit_seq<A: SeqAccess<'de>>(self, value: A) -> Result<Self::Value, A::Error> {
+        let color: Vec3 = Deserialize::deserialize(SeqAccessDeserializer::new(value))?;
+        Ok(Environment::new(EnvironmentMap::Constant(color)))
+    }
+
+    // Environment table, or texture table used as a latitude-longitude image
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let table = Table::deserialize(MapAccessDeserializer::new(map))?;
+        if !MAP_KINDS.iter().any(|kind| table.contains_key(*kind)) {
+            let texture: ColorTexture =
+                Value::Table(table).try_into().map_err(de::Error::custom)?;
+            return Ok(Environment::from(texture));
+        }
+        let desc: EnvironmentTable = Value::Table(table).try_into().map_err(de::Error::custom)?;
+        Ok(Environment {
+            rotation: desc.rotation,
+            intensity: desc.intensity,
+            background: desc.background,
+            ..Environment::new(desc.map)
+        })
+    }
+}
+
 impl Environment {
-    pub fn sample_direction(&self, dir: &Vec3) -> Vec3 {
-        let u = 0.5 + f32::atan2(dir.z, dir.x) / glm::two_pi::<f32>();
-        let v = 0.5 - f32::asin(dir.y) / glm::pi::<f32>();
-        self.sample(u, v)
+    pub fn new(map: EnvironmentMap) -> Self {
+        let distribution = map.distribution();
+        let sun = match &map {
+            EnvironmentMap::Sky(sky) => sky.sun(),
+            _ => None,
+        };
+        Environment {
+            map,
+            rotation: 0.0,
+            intensity: 1.0,
+            background: None,
+            distribution,
+            sun,
+        }
+    }
+
+    /// Radiance arriving from direction `dir`, for lighting
+    pub fn radiance(&self, dir: &Vec3) -> Vec3 {
+        let dir = rotate(&dir.normalize(), -self.rotation);
+        self.map.sample(&dir, 0.0) * self.intensity
+    }
+
+    /// Whether `sample` can pick directions, which is only the case for image and sky maps
+    pub fn is_sampled(&self) -> bool {
+        self.distribution.is_some() || self.sun.is_some()
+    }
+
+    /// Direction picked in proportion to the brightness of the map, and its density with
+    /// respect to solid angle
+    pub fn sample(&self) -> Option<(Vec3, f32)> {
+        let mut rng = rand::thread_rng();
+        let dir = match (&self.distribution, self.sun) {
+            (None, Some((axis, cos_max))) => sample_cone(&axis, cos_max),
+            (Some(_), Some((axis, cos_max))) if rng.gen::<f32>() < SUN_PROBABILITY => {
+                sample_cone(&axis, cos_max)
+            }
+            (Some(distribution), _) => dir_at_uv(&distribution.sample(rng.gen(), rng.gen()).0),
+            (None, None) => return None,
+        };
+        let dir = rotate(&dir, self.rotation);
+        let pdf = self.pdf(&dir);
+        if pdf > 0.0 {
+            Some((dir, pdf))
+        } else {
+            None
+        }
+    }
+
+    /// Density with which `sample` picks `dir`
+    pub fn pdf(&self, dir: &Vec3) -> f32 {
+        let dir = rotate(&dir.normalize(), -self.rotation);
+        let map_pdf = |distribution: &Distribution2D| {
+            let cos_latitude = f32::sqrt(1.0 - dir.y * dir.y);
+            if cos_latitude <= 0.0 {
+                return 0.0;
+            }
+            distribution.pdf(&Sphere::uv_at_dir(&dir))
+                / (2.0 * glm::pi::<f32>() * glm::pi::<f32>() * cos_latitude)
+        };
+        let sun_pdf = |(axis, cos_max): (Vec3, f32)| {
+            if glm::dot(&dir, &axis) >= cos_max {
+                1.0 / (glm::two_pi::<f32>() * (1.0 - cos_max))
+            } else {
+                0.0
+            }
+        };
+        match (&self.distribution, self.sun) {
+            (Some(distribution), Some(sun)) => {
+                (1.0 - SUN_PROBABILITY) * map_pdf(distribution) + SUN_PROBABILITY * sun_pdf(sun)
+            }
+            (Some(distribution), None) => map_pdf(distribution),
+            (None, Some(sun)) => sun_pdf(sun),
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Radiance seen by camera rays in direction `dir`
+    pub fn background(&self, dir: &Vec3) -> Vec3 {
+        match &self.background {
+            Some(background) => background.radiance(dir),
+            None => self.radiance(dir),
+        }
+    }
+
+    /// Applies a change of primaries to all colors
+    pub fn transform(&mut self, matrix: &glm::Mat3) {
+        match &mut self.map {
+            EnvironmentMap::Constant(color) => *color = matrix * *color,
+            EnvironmentMap::Gradient {
+                zenith,
+                horizon,
+                ground,
+            } => {
+                *zenith = matrix * *zenith;
+                *horizon = matrix * *horizon;
+                if let Some(ground) = ground {
+                    *ground = matrix * *ground;
+                }
+            }
+            EnvironmentMap::Image(texture)
+            | EnvironmentMap::Angular(texture)
+            | EnvironmentMap::MirrorBall(texture) => texture.transform(matrix),
+            EnvironmentMap::Cubemap(cubemap) => cubemap.transform(matrix),
+            EnvironmentMap::Sky(sky) => sky.transform(matrix),
+        }
+        if let Some(background) = &mut self.background {
+            background.transform(matrix);
+        }
+    }
+}
+
+/// `dir` rotated about the up axis by `degrees`
+fn rotate(dir: &Vec3, degrees: f32) -> Vec3 {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    glm::vec3(cos * dir.x + sin * dir.z, dir.y, -sin * dir.x + cos * dir.z)
+}
+
+/// Direction uniformly distributed within the cone around `axis`
+fn sample_cone(axis: &Vec3, cos_max: f32) -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let cos_theta = 1.0 - rng.gen::<f32>() * (1.0 - cos_max);
+    let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+    let phi = rng.gen::<f32>() * glm::two_pi::<f32>();
+    let local = glm::vec3(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+    glm::normalize(&transform_to_world(&local, axis))
+}
+
+/// Texture value at `uv`, averaged over `footprint` in uv space
+fn lookup(texture: &ColorTexture, dir: &Vec3, uv: Vec2, footprint: Vec2) -> Vec3 {
+    let at = TexCoord {
+        duvdx: glm::vec2(footprint.x, 0.0),
+        duvdy: glm::vec2(0.0, footprint.y),
+        ..TexCoord::new(uv, *dir)
+    };
+    texture.sample(&at)
+}
+
+/// Position of `dir` in an angular map, where the distance from the center is proportional
+/// to the angle from +z
+fn angular_uv(dir: &Vec3) -> Vec2 {
+    let sin = f32::sqrt(dir.x * dir.x + dir.y * dir.y);
+    let r = if sin > 0.0 {
+        dir.z.clamp(-1.0, 1.0).acos() / glm::pi::<f32>() / sin
+    } else {
+        0.0
+    };
+    glm::vec2(0.5 + 0.5 * dir.x * r, 0.5 - 0.5 * dir.y * r)
+}
+
+/// Position in a mirror ball image of the point reflecting `dir` towards a camera looking
+/// along +z
+fn mirror_ball_uv(dir: &Vec3) -> Vec2 {
+    // Halfway between the reflected direction and the one back to the camera
+    let normal = dir - glm::vec3(0.0, 0.0, 1.0);
+    let length = glm::length(&normal);
+    if length < 1e-6 {
+        // Straight behind the ball, anywhere on the rim
+        return glm::vec2(1.0, 0.5);
+    }
+    let normal = normal / length;
+    glm::vec2(0.5 + 0.5 * normal.x, 0.5 - 0.5 * normal.y)
+}
+
+/// Inverse of `Sphere::uv_at_dir`
+fn dir_at_uv(uv: &Vec2) -> Vec3 {
+    let phi = (uv.x - 0.5) * glm::two_pi::<f32>();
+    let latitude = (0.5 - uv.y) * glm::pi::<f32>();
+    glm::vec3(
+        latitude.cos() * phi.cos(),
+        latitude.sin(),
+        latitude.cos() * phi.sin(),
+    )
+}
+
+impl EnvironmentMap {
+    /// Radiance from `dir`, averaged over a cone about `angle` radians wide for image maps
+    fn sample(&self, dir: &Vec3, angle: f32) -> Vec3 {
+        match self {
+            EnvironmentMap::Constant(color) => *color,
+            EnvironmentMap::Gradient {
+                zenith,
+                horizon,
+                ground,
+            } => {
+                if dir.y >= 0.0 {
+                    glm::mix(horizon, zenith, dir.y)
+                } else {
+                    glm::mix(horizon, &ground.unwrap_or(*horizon), -dir.y)
+                }
+            }
+            EnvironmentMap::Image(texture) => {
+                let footprint = glm::vec2(angle / glm::two_pi::<f32>(), angle / glm::pi::<f32>());
+                lookup(texture, dir, Sphere::uv_at_dir(dir), footprint)
+            }
+            EnvironmentMap::Cubemap(cubemap) => cubemap.sample(dir, angle),
+            // The full circle of the image spans two full turns
+            EnvironmentMap::Angular(texture) => {
+                let footprint = angle / glm::two_pi::<f32>();
+                lookup(
+                    texture,
+                    dir,
+                    angular_uv(dir),
+                    glm::vec2(footprint, footprint),
+                )
+            }
+            EnvironmentMap::MirrorBall(texture) => {
+                let footprint = angle / glm::two_pi::<f32>();
+                lookup(
+                    texture,
+                    dir,
+                    mirror_ball_uv(dir),
+                    glm::vec2(footprint, footprint),
+                )
+            }
+            EnvironmentMap::Sky(sky) => sky.radiance(dir),
+        }
+    }
+
+    /// Luminance over a grid of uv cells, weighted by the solid angle each covers
+    fn distribution(&self) -> Option<Distribution2D> {
+        let (width, height) = match self {
+            EnvironmentMap::Image(ColorTexture::Image(img, _)) => {
+                let size = img.dimensions();
+                (size.x.min(1024.0) as usize, size.y.min(512.0) as usize)
+            }
+            EnvironmentMap::Constant(_) | EnvironmentMap::Gradient { .. } => return None,
+            _ => (512, 256),
+        };
+        let cell = glm::vec2(1.0 / width as f32, 1.0 / height as f32);
+        let angle = glm::two_pi::<f32>() / width as f32;
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let uv = glm::vec2(x as f32 + 0.5, y as f32 + 0.5).component_mul(&cell);
+                let dir = dir_at_uv(&uv);
+                // The sun is too small for the grid and sampled on its own
+                let c = match self {
+                    EnvironmentMap::Sky(sky) => sky.sky_radiance(&dir),
+                    _ => self.sample(&dir, angle),
+                };
+                let luminance = 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+                let cos_latitude = f32::sqrt(1.0 - dir.y * dir.y);
+                // Stray NaNs and infinities in the map would poison the whole distribution
+                let weight = luminance * cos_latitude;
+                func.push(if weight.is_finite() {
+                    weight.max(0.0)
+                } else {
+                    0.0
+                });
+            }
+        }
+        let distribution = Distribution2D::new(&func, width, height);
+        if distribution.integral() > 0.0 {
+            Some(distribution)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorspace::ColorSpace;
+    use crate::texture::{MipMap, Sampler};
+
+    /// Image map with a bright spot on a dim, uneven background
+    fn image_environment() -> Environment {
+        let (width, height) = (32, 16);
+        let buf = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let value = if (x, y) == (20, 5) {
+                    50.0
+                } else {
+                    0.1 + (x * y % 7) as f32
+                };
+                glm::vec3(value, value, value)
+            })
+            .collect();
+        let img = MipMap::new(buf, width, height, Sampler::default());
+        Environment {
+            rotation: 30.0,
+            ..Environment::new(EnvironmentMap::Image(ColorTexture::Image(
+                img,
+                ColorSpace::Linear,
+            )))
+        }
+    }
+
+    fn sky_environment() -> Environment {
+        let sky = Sky::new(
+            &glm::vec3(1.0, 0.5, 0.2),
+            3.0,
+            &glm::vec3(0.3, 0.3, 0.3),
+            20.0,
+            1.0,
+        );
+        Environment::new(EnvironmentMap::Sky(sky))
+    }
+
+    /// Integral of the density over the sphere, summed over a fine grid
+    fn total_pdf(environment: &Environment) -> f32 {
+        let (rings, segments) = (512, 1024);
+        let mut total = 0.0;
+        for i in 0..rings {
+            let theta = (i as f32 + 0.5) / rings as f32 * glm::pi::<f32>();
+            let solid_angle = theta.sin() * glm::pi::<f32>() / rings as f32 * glm::two_pi::<f32>()
+                / segments as f32;
+            for j in 0..segments {
+                let phi = (j as f32 + 0.5) / segments as f32 * glm::two_pi::<f32>();
+                let dir = glm::vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                total += environment.pdf(&dir) * solid_angle;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn uv_round_trip() {
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.75, 0.9), (0.3, 0.01)] {
+            let uv = glm::vec2(u, v);
+            let back = Sphere::uv_at_dir(&dir_at_uv(&uv));
+            assert!(glm::distance(&uv, &back) < 1e-5, "{} {}", uv, back);
+        }
+    }
+
+    #[test]
+    fn uv_cell_solid_angle() {
+        // A small uv cell covers 2π²·cos(latitude) times its area in solid angle
+        let d = 1e-3;
+        for &(u, v) in &[(0.2, 0.5), (0.6, 0.3), (0.9, 0.85)] {
+            let p = dir_at_uv(&glm::vec2(u, v));
+            let du = dir_at_uv(&glm::vec2(u + d, v)) - p;
+            let dv = dir_at_uv(&glm::vec2(u, v + d)) - p;
+            let solid_angle = glm::length(&du.cross(&dv));
+            let latitude = (0.5 - v) * glm::pi::<f32>();
+            let expected = 2.0 * glm::pi::<f32>() * glm::pi::<f32>() * latitude.cos() * d * d;
+            assert!((solid_angle / expected - 1.0).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn sampled_density_matches_pdf() {
+        let environment = image_environment();
+        let distribution = environment.distribution.as_ref().unwrap();
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.33)] {
+            let (uv, pdf) = distribution.sample(u, v);
+            let dir = dir_at_uv(&uv);
+            let cos_latitude = f32::sqrt(1.0 - dir.y * dir.y);
+            let expected = pdf / (2.0 * glm::pi::<f32>() * glm::pi::<f32>() * cos_latitude);
+            let pdf = environment.pdf(&rotate(&dir, environment.rotation));
+            assert!((pdf / expected - 1.0).abs() < 1e-3, "{} {}", pdf, expected);
+        }
+        for _ in 0..100 {
+            let (dir, pdf) = environment.sample().unwrap();
+            assert!((pdf - environment.pdf(&dir)).abs() <= 1e-4 * pdf);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        for environment in &[image_environment(), sky_environment()] {
+            let total = total_pdf(environment);
+            assert!((total - 1.0).abs() < 1e-2, "{}", total);
+        }
     }
 }
@@ -0,0 +1,229 @@
+use std::fmt;
+
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use super::lookup;
+use crate::texture::ColorTexture;
+use crate::{Vec2, Vec3};
+use nalgebra_glm as glm;
+
+/// Six square faces looking along +x, -x, +y, -y, +z and -z, oriented as OpenGL cubemaps
+#[derive(Clone)]
+pub enum Cubemap {
+    Faces(Box<[ColorTexture; 6]>),
+    /// All faces in one image, three faces wide and four tall if `vertical`, with -z upside
+    /// down below -y, otherwise four wide and three tall with -z right of +x
+    Cross {
+        texture: ColorTexture,
+        vertical: bool,
+    },
+}
+
+impl<'de> Deserialize<'de> for Cubemap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(CubemapVisitor)
+    }
+}
+
+struct CubemapVisitor;
+
+impl<'de> Visitor<'de> for CubemapVisitor {
+    type Value = Cubemap;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("six face textures in order, or one cross texture")
+    }
+
+    // Cross image file
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        ColorTexture::deserialize(value.into_deserializer()).map(Cubemap::cross)
+    }
+
+    // Six faces
+    fn visit_seq<A: SeqAccess<'de>>(self, value: A) -> Result<Self::Value, A::Error> {
+        let faces: Box<[ColorTexture; 6]> =
+            Deserialize::deserialize(SeqAccessDeserializer::new(value))?;
+        Ok(Cubemap::Faces(faces))
+    }
+
+    // Cross image file with sampling options
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        ColorTexture::deserialize(MapAccessDeserializer::new(map)).map(Cubemap::cross)
+    }
+}
+
+/// Column and row of each face in the horizontal and vertical cross
+const HORIZONTAL: [(f32, f32); 6] = [
+    (2.0, 1.0),
+    (0.0, 1.0),
+    (1.0, 0.0),
+    (1.0, 2.0),
+    (1.0, 1.0),
+    (3.0, 1.0),
+];
+const VERTICAL: [(f32, f32); 6] = [
+    (2.0, 1.0),
+    (0.0, 1.0),
+    (1.0, 0.0),
+    (1.0, 2.0),
+    (1.0, 1.0),
+    (1.0, 3.0),
+];
+
+impl Cubemap {
+    /// Cross laid out vertically if `texture` is taller than wide
+    pub fn cross(texture: ColorTexture) -> Self {
+        let vertical = texture.texel_size().is_some_and(|texel| texel.x > texel.y);
+        Cubemap::Cross { texture, vertical }
+    }
+
+    /// Radiance from `dir`, averaged over a cone about `angle` radians wide
+    pub fn sample(&self, dir: &Vec3, angle: f32) -> Vec3 {
+        let (face, uv) = face_at_dir(dir);
+        // A face spans a right angle
+        let footprint = angle / glm::half_pi::<f32>();
+        match self {
+            Cubemap::Faces(faces) => {
+                let texture = &faces[face];
+                let half_texel = texture.texel_size().unwrap_or_else(glm::zero) * 0.5;
+                let uv = glm::clamp_vec(&uv, &half_texel, &(glm::vec2(1.0, 1.0) - half_texel));
+                lookup(texture, dir, uv, glm::vec2(footprint, footprint))
+            }
+            Cubemap::Cross { texture, vertical } => {
+                let (layout, size) = if *vertical {
+                    (VERTICAL, glm::vec2(3.0, 4.0))
+                } else {
+                    (HORIZONTAL, glm::vec2(4.0, 3.0))
+                };
+                let uv = if *vertical && face == 5 {
+                    glm::vec2(1.0, 1.0) - uv
+                } else {
+                    uv
+                };
+                // Keep filtering from reaching into neighbouring faces
+                let half_texel = texture
+                    .texel_size()
+                    .unwrap_or_else(glm::zero)
+                    .component_mul(&size)
+                    * 0.5;
+                let uv = glm::clamp_vec(&uv, &half_texel, &(glm::vec2(1.0, 1.0) - half_texel));
+                let (column, row) = layout[face];
+                let uv = (glm::vec2(column, row) + uv).component_div(&size);
+                let footprint = glm::vec2(footprint, footprint).component_div(&size);
+                lookup(texture, dir, uv, footprint)
+            }
+        }
+    }
+
+    pub fn transform(&mut self, matrix: &glm::Mat3) {
+        match self {
+            Cubemap::Faces(faces) => {
+                for face in faces.iter_mut() {
+                    face.transform(matrix);
+                }
+            }
+            Cubemap::Cross { texture, .. } => texture.transform(matrix),
+        }
+    }
+}
+
+/// Index of the face `dir` points at and the uvs within it, with v pointing down
+fn face_at_dir(dir: &Vec3) -> (usize, Vec2) {
+    let abs = glm::abs(dir);
+    let (face, s, t, major) = if abs.x >= abs.y && abs.x >= abs.z {
+        if dir.x > 0.0 {
+            (0, -dir.z, -dir.y, abs.x)
+        } else {
+            (1, dir.z, -dir.y, abs.x)
+        }
+    } else if abs.y >= abs.z {
+        if dir.y > 0.0 {
+            (2, dir.x, dir.z, abs.y)
+        } else {
+            (3, dir.x, -dir.z, abs.y)
+        }
+    } else if dir.z > 0.0 {
+        (4, dir.x, -dir.y, abs.z)
+    } else {
+        (5, -dir.x, -dir.y, abs.z)
+    };
+    (face, glm::vec2(s / major + 1.0, t / major + 1.0) * 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorspace::ColorSpace;
+    use crate::texture::{MipMap, Sampler};
+
+    const AXES: [[f32; 3]; 6] = [
+        [1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+    ];
+
+    /// Cross with four texels square faces whose red channel holds the face index, and -1
+    /// in the empty cells
+    fn cross(layout: &[(f32, f32); 6], columns: u32, rows: u32) -> ColorTexture {
+        let (width, height) = (columns * 4, rows * 4);
+        let buf = (0..width * height)
+            .map(|i| {
+                let cell = ((i % width / 4) as f32, (i / width / 4) as f32);
+                let face = layout.iter().position(|&c| c == cell);
+                glm::vec3(face.map_or(-1.0, |face| face as f32), 0.0, 0.0)
+            })
+            .collect();
+        let img = MipMap::new(buf, width, height, Sampler::default());
+        ColorTexture::Image(img, ColorSpace::Raw)
+    }
+
+    #[test]
+    fn axes_hit_face_centers() {
+        for (i, axis) in AXES.iter().enumerate() {
+            let (face, uv) = face_at_dir(&glm::make_vec3(axis));
+            assert_eq!(face, i);
+            assert!(glm::distance(&uv, &glm::vec2(0.5, 0.5)) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cross_layouts() {
+        // +x, -x, +y, -y, +z and -z in a horizontal cross, then in a vertical one
+        let horizontal = [
+            (2.0, 1.0),
+            (0.0, 1.0),
+            (1.0, 0.0),
+            (1.0, 2.0),
+            (1.0, 1.0),
+            (3.0, 1.0),
+        ];
+        let vertical = [
+            (2.0, 1.0),
+            (0.0, 1.0),
+            (1.0, 0.0),
+            (1.0, 2.0),
+            (1.0, 1.0),
+            (1.0, 3.0),
+        ];
+        let crosses = [
+            (cross(&horizontal, 4, 3), false),
+            (cross(&vertical, 3, 4), true),
+        ];
+        for (texture, vertical) in crosses.iter() {
+            let cubemap = Cubemap::cross(texture.clone());
+            match cubemap {
+                Cubemap::Cross { vertical: v, .. } => assert_eq!(v, *vertical),
+                _ => panic!("expected a cross"),
+            }
+            for (i, axis) in AXES.iter().enumerate() {
+                let radiance = cubemap.sample(&glm::make_vec3(axis), 0.0);
+                assert!((radiance.x - i as f32).abs() < 1e-4, "face {}", i);
+            }
+        }
+    }
+}
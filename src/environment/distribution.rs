@@ -0,0 +1,152 @@
+use crate::Vec2;
+use nalgebra_glm as glm;
+
+/// Piecewise constant distribution over 0..1, proportional to `func`
+#[derive(Clone)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f32>) -> Self {
+        let n = func.len() as f32;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for f in &func {
+            cdf.push(cdf[cdf.len() - 1] + f / n);
+        }
+        let integral = cdf[cdf.len() - 1];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Point for the uniform variable `u`, its density and the segment it lies in
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let last = self.func.len() - 1;
+        let i = match self.cdf.binary_search_by(|c| c.total_cmp(&u)) {
+            Ok(i) => i.min(last),
+            Err(i) => (i - 1).min(last),
+        };
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let offset = if width > 0.0 {
+            (u - self.cdf[i]) / width
+        } else {
+            0.0
+        };
+        let x = (i as f32 + offset) / self.func.len() as f32;
+        (x, self.pdf_at(i), i)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        self.pdf_at(self.index(x))
+    }
+
+    fn pdf_at(&self, i: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[i] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    fn index(&self, x: f32) -> usize {
+        ((x * self.func.len() as f32) as usize).min(self.func.len() - 1)
+    }
+}
+
+/// Piecewise constant distribution over the unit square, picking a row first and then a
+/// column within it
+#[derive(Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Distribution proportional to `func`, given row by row
+    pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+        let rows: Vec<_> = func
+            .chunks_exact(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.marginal.integral()
+    }
+
+    /// Point for the uniform variables `u` and `v`, and its density
+    pub fn sample(&self, u: f32, v: f32) -> (Vec2, f32) {
+        let (y, pdf_y, row) = self.marginal.sample(v);
+        let (x, pdf_x, _) = self.rows[row].sample(u);
+        (glm::vec2(x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, p: &Vec2) -> f32 {
+        let row = self.marginal.index(p.y);
+        self.marginal.pdf_at(row) * self.rows[row].pdf(p.x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_density_matches_pdf() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0, 4.0]);
+        for &u in &[0.0, 0.1, 0.3, 0.5, 0.9, 0.999] {
+            let (x, pdf, i) = distribution.sample(u);
+            assert_ne!(i, 1);
+            assert_eq!(pdf, distribution.pdf(x));
+        }
+        assert_eq!(distribution.pdf(0.6), 3.0 / 2.0);
+    }
+
+    #[test]
+    fn nan_and_zero_functions() {
+        let distribution = Distribution1D::new(vec![1.0, 2.0]);
+        distribution.sample(f32::NAN);
+        let zero = Distribution1D::new(vec![0.0, 0.0]);
+        assert_eq!(zero.sample(0.75), (0.75, 1.0, 1));
+    }
+
+    #[test]
+    fn density_integrates_to_one() {
+        let func: Vec<f32> = (0..12).map(|i| (i * 7 % 5) as f32).collect();
+        let distribution = Distribution2D::new(&func, 4, 3);
+        let n = 48;
+        let mut total = 0.0;
+        for y in 0..n {
+            for x in 0..n {
+                let p = glm::vec2(x as f32 + 0.5, y as f32 + 0.5) / n as f32;
+                total += distribution.pdf(&p) / (n * n) as f32;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-4, "{}", total);
+
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let (p, pdf) = distribution.sample(u, v);
+            assert!((pdf - distribution.pdf(&p)).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::convert::TryFrom;
+
+use serde::Deserialize;
+
+use crate::Vec3;
+use nalgebra_glm as glm;
+
+/// Sky radiance per kcd/m² of the Preetham model, chosen so a clear sky is well exposed
+const SKY_SCALE: f32 = 0.05;
+/// Irradiance of the sun above the atmosphere, in the same units as the sky
+const SUN_IRRADIANCE: f32 = 15.0;
+
+/// Clear daylight sky after Preetham et al., "A Practical Analytic Model for Daylight", with a
+/// sun disk dimmed and reddened by the air it shines through, e.g.
+/// `{ sky = { sun_direction = [1, 0.5, 0.2], turbidity = 3 } }`
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "SkyDesc")]
+pub struct Sky {
+    /// Towards the center of the sun
+    sun: Vec3,
+    /// Cosine of the angular radius of the sun
+    cos_sun: f32,
+    sun_radiance: Vec3,
+    /// Perez distribution coefficients of luminance and the two chromaticities
+    perez: [[f32; 5]; 3],
+    /// Luminance and chromaticities at the zenith, divided by the distribution there
+    zenith: [f32; 3],
+    /// Radiance of the ground below the horizon, lit by sun and sky
+    ground: Vec3,
+    /// Change of primaries applied to the result
+    primaries: glm::Mat3,
+}
+
+#[derive(Deserialize)]
+struct SkyDesc {
+    sun_direction: Vec3,
+    /// Haziness of the atmosphere, from 2 for a very clear sky to 10 for haze
+    #[serde(default = "default_turbidity")]
+    turbidity: f32,
+    /// Reflectance of the ground seen below the horizon
+    #[serde(default = "default_ground_albedo")]
+    ground_albedo: Vec3,
+    /// Angular diameter in degrees
+    #[serde(default = "default_sun_size")]
+    sun_size: f32,
+    #[serde(default = "default_sun_intensity")]
+    sun_intensity: f32,
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+/// Range of turbidities the model was fitted to
+const TURBIDITY_RANGE: (f32, f32) = (2.0, 10.0);
+
+fn default_ground_albedo() -> Vec3 {
+    glm::vec3(0.3, 0.3, 0.3)
+}
+
+fn default_sun_size() -> f32 {
+    0.53
+}
+
+fn default_sun_intensity() -> f32 {
+    1.0
+}
+
+impl TryFrom<SkyDesc> for Sky {
+    type Error = String;
+
+    fn try_from(desc: SkyDesc) -> Result<Self, Self::Error> {
+        let (min, max) = TURBIDITY_RANGE;
+        if !(min..=max).contains(&desc.turbidity) {
+            return Err(format!(
+                "sky turbidity must be between {} and {}, got {}",
+                min, max, desc.turbidity
+            ));
+        }
+        Ok(Sky::new(
+            &desc.sun_direction,
+            desc.turbidity,
+            &desc.ground_albedo,
+            desc.sun_size,
+            desc.sun_intensity,
+        ))
+    }
+}
+
+impl Sky {
+    /// `turbidity` is clamped to the range the model was fitted to, outside of which it gives
+    /// negative aerosol extinction and luminance
+    pub fn new(
+        sun_direction: &Vec3,
+        turbidity: f32,
+        ground_albedo: &Vec3,
+        sun_size: f32,
+        sun_intensity: f32,
+    ) -> Self {
+        let sun = sun_direction.normalize();
+        let t = turbidity.clamp(TURBIDITY_RANGE.0, TURBIDITY_RANGE.1);
+        // The model only covers the sun above the horizon
+        let theta_s = f32::acos(sun.y.clamp(0.0, 1.0)).min(glm::half_pi::<f32>() - 1e-3);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (glm::pi::<f32>() - 2.0 * theta_s);
+        let cubic =
+            |a: f32, b: f32, c: f32, d: f32| ((a * theta_s + b) * theta_s + c) * theta_s + d;
+        let mut zenith = [
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t * t * cubic(0.00166, -0.00375, 0.00209, 0.0)
+                + t * cubic(-0.02903, 0.06377, -0.03202, 0.00394)
+                + cubic(0.11693, -0.21196, 0.06052, 0.25886),
+            t * t * cubic(0.00275, -0.00610, 0.00317, 0.0)
+                + t * cubic(-0.04214, 0.08970, -0.04153, 0.00516)
+                + cubic(0.15346, -0.26756, 0.06670, 0.26688),
+        ];
+        for (value, coefficients) in zenith.iter_mut().zip(&perez) {
+            *value /= self::perez(coefficients, 1.0, theta_s.cos());
+        }
+
+        let cos_sun = f32::cos((sun_size / 2.0).to_radians());
+        let solid_angle = glm::two_pi::<f32>() * (1.0 - cos_sun);
+        let sun_radiance = if sun.y > 0.0 {
+            transmittance(sun.y, t) * SUN_IRRADIANCE * sun_intensity / solid_angle
+        } else {
+            glm::zero()
+        };
+
+        let mut sky = Sky {
+            sun,
+            cos_sun,
+            sun_radiance,
+            perez,
+            zenith,
+            ground: glm::zero(),
+            primaries: glm::identity(),
+        };
+        let irradiance = sky.sky_irradiance() + sun_radiance * solid_angle * sun.y.max(0.0);
+        sky.ground = ground_albedo.component_mul(&irradiance) / glm::pi::<f32>();
+        sky
+    }
+
+    /// Radiance arriving from `dir`, including the sun
+    pub fn radiance(&self, dir: &Vec3) -> Vec3 {
+        let sun = if glm::dot(dir, &self.sun) >= self.cos_sun {
+            self.sun_radiance
+        } else {
+            glm::zero()
+        };
+        self.sky_radiance(dir) + self.primaries * sun
+    }
+
+    /// Radiance arriving from `dir` without the sun
+    pub fn sky_radiance(&self, dir: &Vec3) -> Vec3 {
+        if dir.y < 0.0 {
+            return self.primaries * self.ground;
+        }
+        self.primaries * self.sky(dir)
+    }
+
+    /// Direction towards the center of the sun and the cosine of its angular radius, if it
+    /// is above the horizon
+    pub fn sun(&self) -> Option<(Vec3, f32)> {
+        if self.sun_radiance == glm::zero::<Vec3>() {
+            None
+        } else {
+            Some((self.sun, self.cos_sun))
+        }
+    }
+
+    /// Applies a change of primaries to the output
+    pub fn transform(&mut self, matrix: &glm::Mat3) {
+        self.primaries = matrix * self.primaries;
+    }
+
+    /// Sky in the upper hemisphere, in linear Rec. 709
+    fn sky(&self, dir: &Vec3) -> Vec3 {
+        let cos_theta = dir.y.max(1e-3);
+        let cos_gamma = glm::dot(dir, &self.sun).clamp(-1.0, 1.0);
+        let value = |i: usize| self.zenith[i] * perez(&self.perez[i], cos_theta, cos_gamma);
+        let luminance = value(0) * SKY_SCALE;
+        let (x, y) = (value(1), value(2));
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    /// Irradiance of a surface facing up from the sky alone
+    fn sky_irradiance(&self) -> Vec3 {
+        let (rings, segments) = (32, 64);
+        let mut irradiance: Vec3 = glm::zero();
+        for i in 0..rings {
+            let theta = (i as f32 + 0.5) / rings as f32 * glm::half_pi::<f32>();
+            let solid_angle = theta.sin() * glm::half_pi::<f32>() / rings as f32
+                * glm::two_pi::<f32>()
+                / segments as f32;
+            for j in 0..segments {
+                let phi = (j as f32 + 0.5) / segments as f32 * glm::two_pi::<f32>();
+                let dir = glm::vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance += self.sky(&dir) * theta.cos() * solid_angle;
+            }
+        }
+        irradiance
+    }
+}
+
+/// Perez et al. sky luminance distribution, relative to an unknown scale
+fn perez(c: &[f32; 5], cos_theta: f32, cos_gamma: f32) -> f32 {
+    let gamma = cos_gamma.acos();
+    (1.0 + c[0] * f32::exp(c[1] / cos_theta))
+        * (1.0 + c[2] * f32::exp(c[3] * gamma) + c[4] * cos_gamma * cos_gamma)
+}
+
+/// CIE xyY to linear Rec. 709
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    let xyz = glm::vec3(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+    let rgb = glm::mat3(
+        3.2406, -1.5372, -0.4986, //
+        -0.9689, 1.8758, 0.0415, //
+        0.0557, -0.2040, 1.0570,
+    ) * xyz;
+    glm::max(&rgb, 0.0)
+}
+
+/// Fraction of sunlight at the red, green and blue wavelengths reaching the ground through
+/// Rayleigh scattering and aerosols, for a sun at `cos_theta` from the zenith
+fn transmittance(cos_theta: f32, turbidity: f32) -> Vec3 {
+    // Kasten and Young's relative air mass
+    let zenith_angle = cos_theta.acos().to_degrees();
+    let air_mass = 1.0 / (cos_theta + 0.50572 * f32::powf(96.07995 - zenith_angle, -1.6364));
+    let rayleigh = glm::vec3(0.05, 0.1, 0.24);
+    // Ångström's law with the exponent 1.3, at 650, 550 and 450nm
+    let beta = 0.04608 * turbidity - 0.04586;
+    let aerosol = glm::vec3(1.75, 2.17, 2.82) * beta;
+    glm::exp(&(-(rayleigh + aerosol) * air_mass))
+}
@@ -1,23 +1,25 @@
 mod aabb;
 mod kdtree;
+mod light;
 mod mesh;
 mod plane;
 mod scene;
 mod sphere;
-mod tracer;
 
 use serde::Deserialize;
 
 pub use self::aabb::*;
 pub use self::kdtree::*;
+pub use self::light::*;
 pub use self::mesh::*;
 pub use self::plane::*;
 pub use self::scene::*;
 pub use self::sphere::*;
-pub use self::tracer::*;
 
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::texture::TexCoord;
+use crate::vec::{glm, perpendicular};
 
 use crate::{Vec2, Vec3};
 
@@ -32,8 +34,124 @@ pub trait Traceable {
 pub struct RayHit {
     pub t: f32,
     pub point: Vec3,
+    /// Normal of the actual surface, flipped to face the incoming ray
+    pub geometric_normal: Vec3,
+    /// Interpolated or mapped normal used for shading, on the same side as the geometric one
+    pub shading_normal: Vec3,
+    /// Interpolated normal as given by the surface, never flipped
     pub normal: Vec3,
+    /// Partial derivatives of the position with respect to u and v, not flipped with the normal
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub uv: Vec2,
+    /// Change of uv to the hits of neighbouring pixels, zero if unknown
+    pub duvdx: Vec2,
+    pub duvdy: Vec2,
+    /// Whether the ray hit the outside of the surface
+    pub front_face: bool,
+}
+
+impl RayHit {
+    /// `normal` is the outward facing normal of the surface, also used for shading until
+    /// `with_shading_normal` is called
+    pub fn new(t: f32, point: Vec3, normal: Vec3, uv: Vec2, front_face: bool) -> Self {
+        let geometric_normal = if front_face { normal } else { -normal };
+        let dpdu = perpendicular(&normal);
+        RayHit {
+            t,
+            point,
+            geometric_normal,
+            shading_normal: geometric_normal,
+            normal,
+            dpdu,
+            dpdv: normal.cross(&dpdu),
+            uv,
+            duvdx: glm::zero(),
+            duvdy: glm::zero(),
+            front_face,
+        }
+    }
+
+    pub fn with_derivatives(self, dpdu: Vec3, dpdv: Vec3) -> Self {
+        RayHit { dpdu, dpdv, ..self }
+    }
+
+    /// Uses `normal` for shading, flipped to the side of the surface that was hit
+    pub fn with_shading_normal(self, normal: Vec3) -> Self {
+        let normal = normal.normalize();
+        let shading_normal = if glm::dot(&normal, &self.geometric_normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        RayHit {
+            shading_normal,
+            normal,
+            ..self
+        }
+    }
+
+    /// Estimates `duvdx` and `duvdy` by intersecting the differentials of `ray` with the
+    /// tangent plane at the hit
+    pub fn with_differentials(self, ray: &Ray) -> Self {
+        let differentials = match &ray.differentials {
+            Some(differentials) => differentials,
+            None => return self,
+        };
+        let n = self.geometric_normal;
+        let offset = |origin: &Vec3, direction: &Vec3| {
+            let denom = glm::dot(&n, direction);
+            if denom.abs() < 1e-8 {
+                return None;
+            }
+            let t = glm::dot(&n, &(self.point - origin)) / denom;
+            Some(origin + direction * t - self.point)
+        };
+        let (dpdx, dpdy) = match (
+            offset(&differentials.rx_origin, &differentials.rx_direction),
+            offset(&differentials.ry_origin, &differentials.ry_direction),
+        ) {
+            (Some(dpdx), Some(dpdy)) => (dpdx, dpdy),
+            _ => return self,
+        };
+        // Least squares solution of dp = dpdu * du + dpdv * dv
+        let (uu, uv, vv) = (
+            glm::dot(&self.dpdu, &self.dpdu),
+            glm::dot(&self.dpdu, &self.dpdv),
+            glm::dot(&self.dpdv, &self.dpdv),
+        );
+        let det = uu * vv - uv * uv;
+        if det.abs() < 1e-12 {
+            return self;
+        }
+        let solve = |dp: Vec3| {
+            let (a, b) = (glm::dot(&self.dpdu, &dp), glm::dot(&self.dpdv, &dp));
+            Vec2::new((vv * a - uv * b) / det, (uu * b - uv * a) / det)
+        };
+        RayHit {
+            duvdx: solve(dpdx),
+            duvdy: solve(dpdy),
+            ..self
+        }
+    }
+
+    pub fn tex_coord(&self) -> TexCoord {
+        TexCoord {
+            uv: self.uv,
+            point: self.point,
+            duvdx: self.duvdx,
+            duvdy: self.duvdy,
+        }
+    }
+
+    /// Shading normal on the outside of the surface, regardless of which side was hit
+    pub fn outward_shading_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.shading_normal
+        } else {
+            -self.shading_normal
+        }
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -73,6 +191,8 @@ pub struct Object {
 pub struct TraceResult<'a> {
     pub hit: RayHit,
     pub material: &'a Material,
+    /// Index of the object within the traced collection
+    pub object: usize,
 }
 
 impl Traceable for Object {
@@ -82,6 +202,7 @@ impl Traceable for Object {
             .map(|hit| TraceResult {
                 hit,
                 material: &self.material,
+                object: 0,
             })
     }
 }
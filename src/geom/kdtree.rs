@@ -13,6 +13,8 @@ pub enum KdTree<T> {
     },
     Node {
         bounds: AABB,
+        dim: usize,
+        pos: f32,
         left: Box<KdTree<T>>,
         right: Box<KdTree<T>>,
     },
@@ -42,6 +44,8 @@ impl<T: Bounds + Clone + Sync> KdTree<T> {
                 let (left_geoms, right_geoms) = partition_dimension(geoms, split.pos, split.dim);
                 KdTree::Node {
                     bounds,
+                    dim: split.dim,
+                    pos: split.pos,
                     left: Box::new(KdTree::build(left, left_geoms)),
                     right: Box::new(KdTree::build(right, right_geoms)),
                 }
@@ -49,6 +53,38 @@ impl<T: Bounds + Clone + Sync> KdTree<T> {
             _ => KdTree::Leaf { bounds, geoms },
         }
     }
+
+    /// Every geometry in the tree exactly once, even if it straddles split planes
+    pub fn unique_items(&self) -> Vec<&T> {
+        fn collect<'a, T: Bounds>(
+            tree: &'a KdTree<T>,
+            exclude: &mut Vec<(usize, f32)>,
+            out: &mut Vec<&'a T>,
+        ) {
+            match tree {
+                KdTree::Leaf { geoms, .. } => out.extend(geoms.iter().filter(|g| {
+                    let bounds = g.bounds();
+                    // Geometry reaching this far left of a split was already collected on the left
+                    !exclude.iter().any(|&(dim, pos)| bounds.min[dim] <= pos)
+                })),
+                KdTree::Node {
+                    dim,
+                    pos,
+                    left,
+                    right,
+                    ..
+                } => {
+                    collect(left, exclude, out);
+                    exclude.push((*dim, *pos));
+                    collect(right, exclude, out);
+                    exclude.pop();
+                }
+            }
+        }
+        let mut items = Vec::new();
+        collect(self, &mut Vec::new(), &mut items);
+        items
+    }
 }
 
 #[derive(PartialEq, PartialOrd)]
@@ -187,6 +223,7 @@ impl<T: Geometry> Geometry for KdTree<T> {
                 bounds,
                 left,
                 right,
+                ..
             } if bounds.intersects(r) => {
                 let mut max = max;
                 let left = left.intersection(r, min, max);
@@ -0,0 +1,146 @@
+use nalgebra_glm as glm;
+use rand::prelude::*;
+
+use super::*;
+use crate::vec::transform_to_world;
+
+pub struct SurfacePoint {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+pub trait Surface {
+    fn area(&self) -> f32;
+
+    /// Uniformly distributed point on the surface
+    fn sample_point(&self) -> SurfacePoint;
+}
+
+pub struct LightSample {
+    pub point: SurfacePoint,
+    /// Probability density with respect to solid angle as seen from the shading point
+    pub pdf: f32,
+}
+
+#[derive(Clone)]
+enum Emitter {
+    Sphere(Sphere),
+    Plane(Plane),
+    Triangles { tris: Vec<Triangle>, cdf: Vec<f32> },
+}
+
+/// Emissive object of a scene, referenced by its index in the object list
+#[derive(Clone)]
+pub struct Light {
+    pub object: usize,
+    emitter: Emitter,
+}
+
+impl Light {
+    pub fn new(object: usize, geometry: &GeomType) -> Self {
+        let emitter = match geometry {
+            GeomType::Sphere(s) => Emitter::Sphere(s.clone()),
+            GeomType::Plane(p) => Emitter::Plane(p.clone()),
+            GeomType::Mesh(m) => {
+                let tris: Vec<Triangle> = m.triangles().into_iter().cloned().collect();
+                let cdf = tris
+                    .iter()
+                    .scan(0.0, |total, tri| {
+                        *total += tri.area();
+                        Some(*total)
+                    })
+                    .collect();
+                Emitter::Triangles { tris, cdf }
+            }
+        };
+        Light { object, emitter }
+    }
+
+    pub fn sample(&self, from: &Vec3) -> Option<LightSample> {
+        match &self.emitter {
+            Emitter::Sphere(s) => sample_sphere(s, from),
+            Emitter::Plane(p) => area_to_solid_angle(from, p.sample_point(), p.area()),
+            Emitter::Triangles { tris, cdf } => {
+                let total = *cdf.last()?;
+                let x = rand::thread_rng().gen::<f32>() * total;
+                let idx = match cdf.binary_search_by(|c| c.partial_cmp(&x).unwrap()) {
+                    Ok(i) | Err(i) => i.min(tris.len() - 1),
+                };
+                area_to_solid_angle(from, tris[idx].sample_point(), total)
+            }
+        }
+    }
+
+    /// Density with which `sample` returns the point `hit` on this light
+    pub fn pdf(&self, from: &Vec3, hit: &RayHit) -> f32 {
+        let area = match &self.emitter {
+            Emitter::Sphere(s) => match cone_cos_max(s, from) {
+                Some(cos_max) => return 1.0 / (glm::two_pi::<f32>() * (1.0 - cos_max)),
+                None => s.area(),
+            },
+            Emitter::Plane(p) => p.area(),
+            Emitter::Triangles { cdf, .. } => *cdf.last().unwrap_or(&0.0),
+        };
+        area_pdf(from, &hit.point, &hit.geometric_normal, area).unwrap_or(0.0)
+    }
+}
+
+fn area_pdf(from: &Vec3, point: &Vec3, normal: &Vec3, area: f32) -> Option<f32> {
+    let to_light = point - from;
+    let dist2 = glm::dot(&to_light, &to_light);
+    let cos_light = f32::abs(glm::dot(normal, &to_light.normalize()));
+    if cos_light < 1e-6 || area <= 0.0 {
+        None
+    } else {
+        Some(dist2 / (cos_light * area))
+    }
+}
+
+fn area_to_solid_angle(from: &Vec3, surface: SurfacePoint, area: f32) -> Option<LightSample> {
+    let pdf = area_pdf(from, &surface.point, &surface.normal, area)?;
+    Some(LightSample {
+        point: surface,
+        pdf,
+    })
+}
+
+/// Cosine of the half-angle of the cone subtended by the sphere, `None` from inside it
+fn cone_cos_max(sphere: &Sphere, from: &Vec3) -> Option<f32> {
+    let to_center = sphere.center - from;
+    let dist2 = glm::dot(&to_center, &to_center);
+    let r2 = sphere.radius * sphere.radius;
+    if dist2 <= r2 {
+        None
+    } else {
+        Some(f32::sqrt(1.0 - r2 / dist2))
+    }
+}
+
+/// Samples the cone of directions subtended by the sphere, falling back to area sampling inside it
+fn sample_sphere(sphere: &Sphere, from: &Vec3) -> Option<LightSample> {
+    let cos_max = match cone_cos_max(sphere, from) {
+        Some(cos_max) => cos_max,
+        None => return area_to_solid_angle(from, sphere.sample_point(), sphere.area()),
+    };
+    let to_center = sphere.center - from;
+    let mut rng = rand::thread_rng();
+    let cos_theta = 1.0 - rng.gen::<f32>() * (1.0 - cos_max);
+    let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+    let phi = rng.gen::<f32>() * glm::two_pi::<f32>();
+    let local = glm::vec3(
+        sin_theta * f32::sin(phi),
+        cos_theta,
+        sin_theta * f32::cos(phi),
+    );
+    let direction = transform_to_world(&local, &to_center.normalize());
+    let hit = sphere.intersection(&Ray::new(*from, direction), 0.0, f32::MAX)?;
+    Some(LightSample {
+        point: SurfacePoint {
+            point: hit.point,
+            normal: hit.geometric_normal,
+            uv: hit.uv,
+        },
+        pdf: 1.0 / (glm::two_pi::<f32>() * (1.0 - cos_max)),
+    })
+}
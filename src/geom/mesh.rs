@@ -1,11 +1,13 @@
 use std::path::Path;
 
 use nalgebra_glm as glm;
+use rand::prelude::*;
 use serde::{Deserialize, Deserializer};
 
 use super::*;
 use crate::obj;
 use crate::ray::Ray;
+use crate::vec::perpendicular;
 use crate::{Vec2, Vec3};
 
 #[derive(Clone)]
@@ -18,6 +20,9 @@ pub struct Vertex {
 #[derive(Clone)]
 pub struct Triangle {
     verts: [Vertex; 3],
+    normal: Vec3,
+    dpdu: Vec3,
+    dpdv: Vec3,
 }
 
 #[derive(Clone)]
@@ -27,8 +32,24 @@ pub struct Mesh {
 
 impl Triangle {
     pub fn new(v1: Vertex, v2: Vertex, v3: Vertex) -> Self {
+        let (e1, e2) = (v2.pos - v1.pos, v3.pos - v1.pos);
+        let (d1, d2) = (v2.uv - v1.uv, v3.uv - v1.uv);
+        let det = d1.x * d2.y - d1.y * d2.x;
+        let normal = e1.cross(&e2).normalize();
+        let (dpdu, dpdv) = if det.abs() > 1e-9 {
+            let dpdu = (e1 * d2.y - e2 * d1.y) / det;
+            let dpdv = (e2 * d1.x - e1 * d2.x) / det;
+            (dpdu, dpdv)
+        } else {
+            // No usable texture coordinates, any frame in the triangle's plane will do
+            let dpdu = perpendicular(&normal);
+            (dpdu, normal.cross(&dpdu))
+        };
         Triangle {
             verts: [v1, v2, v3],
+            normal,
+            dpdu,
+            dpdv,
         }
     }
 
@@ -65,8 +86,8 @@ impl Geometry for Triangle {
         let pvec = r.direction.cross(&e2);
         let det = e1.dot(&pvec);
 
-        // Cull backfaces by ignoring negative det
-        if det.is_sign_positive() {
+        // Positive det means the ray hit the front face
+        if det.abs() > f32::EPSILON {
             let idet = 1.0 / det;
             let tvec = r.origin - v0;
             let qvec = tvec.cross(&e1);
@@ -76,12 +97,10 @@ impl Geometry for Triangle {
             {
                 let point = r.point_at(t);
                 let Vertex { uv, normal, .. } = self.interpolate(&point);
-                Some(RayHit {
-                    t,
-                    point,
-                    normal,
-                    uv,
-                })
+                let hit = RayHit::new(t, point, self.normal, uv, det > 0.0)
+                    .with_derivatives(self.dpdu, self.dpdv)
+                    .with_shading_normal(normal);
+                Some(hit)
             } else {
                 None
             }
@@ -91,6 +110,27 @@ impl Geometry for Triangle {
     }
 }
 
+impl Surface for Triangle {
+    fn area(&self) -> f32 {
+        let (p0, p1, p2) = self.positions();
+        0.5 * glm::length(&(p1 - p0).cross(&(p2 - p0)))
+    }
+
+    fn sample_point(&self) -> SurfacePoint {
+        let mut rng = rand::thread_rng();
+        let su = f32::sqrt(rng.gen::<f32>());
+        let (b0, b1) = (1.0 - su, rng.gen::<f32>() * su);
+        let b2 = 1.0 - b0 - b1;
+        let [v0, v1, v2] = &self.verts;
+        let (p0, p1, p2) = self.positions();
+        SurfacePoint {
+            point: p0 * b0 + p1 * b1 + p2 * b2,
+            normal: self.normal,
+            uv: v0.uv * b0 + v1.uv * b1 + v2.uv * b2,
+        }
+    }
+}
+
 impl Bounds for Triangle {
     fn bounds(&self) -> AABB {
         AABB::from(self.verts.iter().map(|v| &v.pos))
@@ -103,6 +143,10 @@ impl Mesh {
         let tree = KdTree::new(tris);
         Ok(Mesh { tree })
     }
+
+    pub fn triangles(&self) -> Vec<&Triangle> {
+        self.tree.unique_items()
+    }
 }
 
 impl Geometry for Mesh {
@@ -1,4 +1,5 @@
 use nalgebra_glm as glm;
+use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use super::*;
@@ -44,12 +45,7 @@ impl Geometry for Plane {
                 let u = glm::dot(&x.normalize(), &(point - self.points[0])) / glm::length(&x);
                 let v = glm::dot(&y.normalize(), &(point - self.points[0])) / glm::length(&y);
                 let uv = glm::vec2(u, v);
-                Some(RayHit {
-                    t,
-                    point,
-                    normal,
-                    uv,
-                })
+                Some(RayHit::new(t, point, normal, uv, denom < 0.0).with_derivatives(x, y))
             } else {
                 None
             }
@@ -59,6 +55,26 @@ impl Geometry for Plane {
     }
 }
 
+impl Surface for Plane {
+    fn area(&self) -> f32 {
+        let side1 = self.points[1] - self.points[0];
+        let side2 = self.points[3] - self.points[0];
+        glm::length(&side1.cross(&side2))
+    }
+
+    fn sample_point(&self) -> SurfacePoint {
+        let mut rng = rand::thread_rng();
+        let uv = glm::vec2(rng.gen::<f32>(), rng.gen::<f32>());
+        let side1 = self.points[1] - self.points[0];
+        let side2 = self.points[3] - self.points[0];
+        SurfacePoint {
+            point: self.points[0] + uv.x * side1 + uv.y * side2,
+            normal: self.normal(),
+            uv,
+        }
+    }
+}
+
 impl Bounds for Plane {
     fn bounds(&self) -> AABB {
         AABB::from(self.points.iter())
@@ -1,26 +1,136 @@
 use serde::Deserialize;
 
 use super::*;
+use crate::environment::Environment;
 use crate::ray::Ray;
-use crate::texture::ColorTexture;
+use crate::vec::*;
 
 #[derive(Deserialize, Clone)]
+#[serde(from = "SceneDesc")]
 pub struct Scene {
     objects: Vec<Object>,
-    pub environment: ColorTexture,
+    lights: Vec<Light>,
+    pub environment: Environment,
 }
 
-impl Traceable for Scene {
-    fn trace(&self, ray: &Ray, min: f32, max: f32) -> Option<TraceResult> {
+#[derive(Deserialize)]
+struct SceneDesc {
+    objects: Vec<Object>,
+    environment: Environment,
+}
+
+impl From<SceneDesc> for Scene {
+    fn from(desc: SceneDesc) -> Self {
+        let mut scene = Scene::new(desc.environment);
+        for object in desc.objects {
+            scene.add(object);
+        }
+        scene
+    }
+}
+
+impl Scene {
+    pub fn new(environment: Environment) -> Self {
+        Scene {
+            objects: Vec::new(),
+            lights: Vec::new(),
+            environment,
+        }
+    }
+
+    pub fn add(&mut self, object: Object) {
+        if object.material.is_emissive() {
+            let light = Light::new(self.objects.len(), &object.geometry);
+            self.lights.push(light);
+        }
+        self.objects.push(object);
+    }
+
+    /// Converts all colors in the scene to other primaries
+    pub fn transform_colors(&mut self, matrix: &glm::Mat3) {
+        for object in &mut self.objects {
+            object.material.transform_colors(matrix);
+        }
+        self.environment.transform(matrix);
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Number of light sources picked from by light sampling, counting an importance
+    /// sampled environment as one
+    pub fn light_count(&self) -> usize {
+        self.lights.len() + self.environment.is_sampled() as usize
+    }
+
+    pub fn sample_environment(&self, dir: &Vec3) -> Vec3 {
+        self.environment.radiance(dir)
+    }
+
+    /// Environment as seen directly by the camera
+    pub fn sample_background(&self, dir: &Vec3) -> Vec3 {
+        self.environment.background(dir)
+    }
+
+    /// Density of sampling the point `hit` on `object` from `from` through the lights,
+    /// zero if the object is not emissive
+    pub fn light_pdf(&self, object: usize, from: &Vec3, hit: &RayHit) -> f32 {
+        self.lights
+            .iter()
+            .find(|light| light.object == object)
+            .map_or(0.0, |light| {
+                light.pdf(from, hit) / self.light_count() as f32
+            })
+    }
+
+    /// Density of sampling `dir` through the lights
+    pub fn environment_pdf(&self, dir: &Vec3) -> f32 {
+        if self.environment.is_sampled() {
+            self.environment.pdf(dir) / self.light_count() as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether anything blocks the segment between two points
+    pub fn occluded(&self, from: &Vec3, to: &Vec3) -> bool {
+        let dir = to - from;
+        let dist = glm::length(&dir);
+        self.blocked(from, &(dir / dist), dist - 0.001)
+    }
+
+    /// Whether anything lies within `dist` of `from` along `dir`
+    pub fn blocked(&self, from: &Vec3, dir: &Vec3, dist: f32) -> bool {
+        let ray = Ray::new(*from, *dir);
+        self.closest_hit(&ray, 0.001, dist).is_some()
+    }
+
+    /// Nearest intersection with the geometric surface, before any normal mapping
+    fn closest_hit(&self, ray: &Ray, min: f32, max: f32) -> Option<TraceResult> {
         let mut max = max;
         let mut result = None;
-        for obj in &self.objects {
+        for (i, obj) in self.objects.iter().enumerate() {
             let traced = obj.trace(ray, min, max);
             if let Some(TraceResult { hit, .. }) = &traced {
                 max = hit.t;
-                result = traced;
+                result = traced.map(|t| TraceResult { object: i, ..t });
             }
         }
         result
     }
 }
+
+impl Traceable for Scene {
+    fn trace(&self, ray: &Ray, min: f32, max: f32) -> Option<TraceResult> {
+        self.closest_hit(ray, min, max).map(|mut result| {
+            result.hit = result.hit.with_differentials(ray);
+            result.hit.shading_normal = result.material.shading_normal(&result.hit);
+            result
+        })
+    }
+}
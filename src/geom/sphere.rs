@@ -1,4 +1,5 @@
 use nalgebra_glm as glm;
+use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use super::*;
@@ -22,27 +23,11 @@ impl Geometry for Sphere {
         if delta > 0.0 {
             let t = (-b - f32::sqrt(b * b - a * c)) / a;
             if t > min && t < max {
-                let point = r.point_at(t);
-                let normal = (point - self.center) / self.radius;
-                let uv = Self::uv_at_dir(&(point - self.center).normalize());
-                return Some(RayHit {
-                    t,
-                    point,
-                    normal,
-                    uv,
-                });
+                return Some(self.hit_at(r, t));
             }
             let t = (-b + f32::sqrt(b * b - a * c)) / a;
             if t > min && t < max {
-                let point = r.point_at(t);
-                let normal = (point - self.center) / self.radius;
-                let uv = Self::uv_at_dir(&(point - self.center).normalize());
-                Some(RayHit {
-                    t,
-                    point,
-                    normal,
-                    uv,
-                })
+                Some(self.hit_at(r, t))
             } else {
                 None
             }
@@ -61,7 +46,44 @@ impl Bounds for Sphere {
     }
 }
 
+impl Surface for Sphere {
+    fn area(&self) -> f32 {
+        2.0 * glm::two_pi::<f32>() * self.radius * self.radius
+    }
+
+    fn sample_point(&self) -> SurfacePoint {
+        let mut rng = rand::thread_rng();
+        let y = 1.0 - 2.0 * rng.gen::<f32>();
+        let r = f32::sqrt(f32::max(0.0, 1.0 - y * y));
+        let phi = rng.gen::<f32>() * glm::two_pi::<f32>();
+        let normal = glm::vec3(r * f32::cos(phi), y, r * f32::sin(phi));
+        SurfacePoint {
+            point: self.center + self.radius * normal,
+            normal,
+            uv: Self::uv_at_dir(&normal),
+        }
+    }
+}
+
 impl Sphere {
+    fn hit_at(&self, r: &Ray, t: f32) -> RayHit {
+        let point = r.point_at(t);
+        let normal = (point - self.center) / self.radius;
+        let uv = Self::uv_at_dir(&normal.normalize());
+        // Longitude increases with u and latitude decreases with v
+        let local = point - self.center;
+        let phi = f32::atan2(local.z, local.x);
+        let dpdu = glm::vec3(-local.z, 0.0, local.x) * glm::two_pi::<f32>();
+        let dpdtheta = glm::vec3(
+            -local.y * phi.cos(),
+            f32::hypot(local.x, local.z),
+            -local.y * phi.sin(),
+        );
+        let dpdv = dpdtheta * -glm::pi::<f32>();
+        let front_face = glm::dot(&r.direction, &normal) < 0.0;
+        RayHit::new(t, point, normal, uv, front_face).with_derivatives(dpdu, dpdv)
+    }
+
     pub fn uv_at_dir(dir: &Vec3) -> Vec2 {
         let u = 0.5 + f32::atan2(dir.z, dir.x) / glm::two_pi::<f32>();
         let v = 0.5 - f32::asin(dir.y) / glm::pi::<f32>();
@@ -0,0 +1,155 @@
+mod ao;
+mod debug;
+mod direct;
+mod path;
+
+use rand::prelude::*;
+use serde::Deserialize;
+
+pub use ao::*;
+pub use debug::*;
+pub use direct::*;
+pub use path::*;
+
+use crate::config::RenderParams;
+use crate::geom::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::{TexCoord, Texture as _};
+use crate::vec::*;
+
+pub trait Integrator: Sync {
+    /// Light arriving at the origin of `ray` from its direction
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vec3;
+
+    fn output(&self) -> Output {
+        Output::Radiance
+    }
+}
+
+/// How the results of an integrator are turned into pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Exposed, tone mapped and encoded with the output transfer
+    Radiance,
+    /// Colors in the working space, converted to Rec. 709 and encoded with the output
+    /// transfer, without exposure or tone mapping
+    Color,
+    /// Data written out as-is
+    Raw,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorType {
+    #[default]
+    Path,
+    Direct,
+    #[serde(alias = "ao")]
+    AmbientOcclusion,
+    #[serde(alias = "normals")]
+    Normal,
+    Uv,
+    Depth,
+    Albedo,
+    ObjectId,
+}
+
+pub fn build(params: &RenderParams) -> Box<dyn Integrator> {
+    match params.integrator {
+        IntegratorType::Path => Box::new(PathTracer {
+            max_depth: params.max_light_bounces,
+            roulette_depth: params.roulette_depth,
+        }),
+        IntegratorType::Direct => Box::new(DirectLighting),
+        IntegratorType::AmbientOcclusion => Box::new(AmbientOcclusion {
+            distance: params.ao_distance,
+        }),
+        IntegratorType::Normal => Box::new(DebugView::Normal),
+        IntegratorType::Uv => Box::new(DebugView::Uv),
+        IntegratorType::Depth => Box::new(DebugView::Depth {
+            range: params.depth_range,
+        }),
+        IntegratorType::Albedo => Box::new(DebugView::Albedo),
+        IntegratorType::ObjectId => Box::new(DebugView::ObjectId),
+    }
+}
+
+/// Emission of a surface hit by a bounced ray, weighed against the light samples taken at
+/// the bounce origin. `bounce` holds that origin and the BRDF pdf of the bounce, if any.
+fn emitted(scene: &Scene, result: &TraceResult, bounce: Option<(Vec3, f32)>) -> Vec3 {
+    let TraceResult {
+        material,
+        hit,
+        object,
+    } = result;
+    let emitted = material.emission.sample(&hit.tex_coord());
+    match bounce {
+        Some((from, brdf_pdf)) => {
+            emitted * power_heuristic(brdf_pdf, scene.light_pdf(*object, &from, hit))
+        }
+        None => emitted,
+    }
+}
+
+/// Environment light reached by a bounced ray that escaped the scene, weighed against the
+/// light samples taken at the bounce origin like `emitted`
+fn escaped(scene: &Scene, dir: &Vec3, bounce: Option<(Vec3, f32)>) -> Vec3 {
+    let environment = scene.sample_environment(dir);
+    match bounce {
+        Some((_, brdf_pdf)) => environment * power_heuristic(brdf_pdf, scene.environment_pdf(dir)),
+        None => environment,
+    }
+}
+
+/// Estimates light arriving directly from one randomly chosen emitter, or from the
+/// environment if it can be importance sampled
+fn direct_lighting(w0: &Vec3, hit: &RayHit, material: &Material, scene: &Scene) -> Vec3 {
+    let lights = scene.lights();
+    let count = scene.light_count();
+    if count == 0 {
+        return glm::zero();
+    }
+    let choice = rand::thread_rng().gen_range(0, count);
+    // Direction, distance and density of the sample, with the radiance it carries
+    let (wi, dist, pdf, radiance) = match lights.get(choice) {
+        Some(light) => {
+            let sample = match light.sample(&hit.point) {
+                Some(sample) => sample,
+                None => return glm::zero(),
+            };
+            let to_light = sample.point.point - hit.point;
+            let dist = glm::length(&to_light);
+            if dist < 0.001 {
+                return glm::zero();
+            }
+            let emitter = &scene.objects()[light.object].material;
+            let emission = emitter
+                .emission
+                .sample(&TexCoord::new(sample.point.uv, sample.point.point));
+            (to_light / dist, dist - 0.001, sample.pdf, emission)
+        }
+        None => match scene.environment.sample() {
+            Some((wi, pdf)) => (wi, f32::MAX, pdf, scene.sample_environment(&wi)),
+            None => return glm::zero(),
+        },
+    };
+    // Transmissive materials also receive light from behind the surface
+    let costheta = f32::abs(glm::dot(&hit.shading_normal, &wi));
+    let brdf = material.eval(w0, &wi, hit);
+    if brdf == glm::zero::<Vec3>() || scene.blocked(&hit.point, &wi, dist) {
+        return glm::zero();
+    }
+    let light_pdf = pdf / count as f32;
+    let weight = power_heuristic(light_pdf, material.pdf(w0, &wi, hit));
+    brdf.component_mul(&radiance) * costheta * weight / light_pdf
+}
+
+fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a.is_infinite() {
+        1.0
+    } else {
+        a / (a + b)
+    }
+}
@@ -0,0 +1,30 @@
+use rand::prelude::*;
+
+use super::*;
+use crate::vec::transform_to_world;
+
+/// Fraction of the hemisphere above each point that is unoccluded within `distance`
+pub struct AmbientOcclusion {
+    pub distance: f32,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vec3 {
+        let hit = match scene.trace(ray, 0.001, f32::MAX) {
+            Some(TraceResult { hit, .. }) => hit,
+            None => return glm::vec3(1.0, 1.0, 1.0),
+        };
+        let mut rng = rand::thread_rng();
+        let r2: f32 = rng.gen();
+        let r = f32::sqrt(r2);
+        let phi = rng.gen::<f32>() * glm::two_pi::<f32>();
+        let local = glm::vec3(r * f32::sin(phi), f32::sqrt(1.0 - r2), r * f32::cos(phi));
+        let direction = transform_to_world(&local, &hit.shading_normal);
+        let probe = Ray::new(hit.point, direction);
+        if scene.trace(&probe, 0.001, self.distance).is_some() {
+            glm::zero()
+        } else {
+            glm::vec3(1.0, 1.0, 1.0)
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use super::*;
+
+/// Visualizations of the data found at the first hit along each camera ray
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// Interpolated normal as given by the surface, mapped from [-1, 1] to [0, 1], so that
+    /// normals pointing inwards show up
+    Normal,
+    /// Texture coordinates wrapped into [0, 1) like repeating textures, in red and green
+    Uv,
+    /// Hit distance, black at the camera and white at `range`
+    Depth { range: f32 },
+    /// Unlit albedo texture sample, encoded like the rendered colors but without exposure
+    /// or tone mapping
+    Albedo,
+    /// Distinct colour per object
+    ObjectId,
+}
+
+impl Integrator for DebugView {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vec3 {
+        let TraceResult {
+            material,
+            hit,
+            object,
+        } = match scene.trace(ray, 0.001, f32::MAX) {
+            Some(result) => result,
+            None => return glm::zero(),
+        };
+        match *self {
+            DebugView::Normal => (hit.normal + glm::vec3(1.0, 1.0, 1.0)) * 0.5,
+            DebugView::Uv => glm::vec3(hit.uv.x.rem_euclid(1.0), hit.uv.y.rem_euclid(1.0), 0.0),
+            DebugView::Depth { range } => {
+                let depth = hit.t * glm::length(&ray.direction) / range;
+                glm::vec3(depth, depth, depth)
+            }
+            DebugView::Albedo => material.albedo.sample(&hit.tex_coord()),
+            DebugView::ObjectId => id_color(object),
+        }
+    }
+
+    fn output(&self) -> Output {
+        match self {
+            DebugView::Albedo => Output::Color,
+            _ => Output::Raw,
+        }
+    }
+}
+
+/// Spreads consecutive indices around the hue circle using the golden ratio
+fn id_color(id: usize) -> Vec3 {
+    let hue = (id as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    glm::vec3(r, g, b) * 0.8 + glm::vec3(0.2, 0.2, 0.2)
+}
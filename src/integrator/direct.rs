@@ -0,0 +1,31 @@
+use super::*;
+
+/// Single-bounce preview: emission plus direct light from emitters and the environment
+pub struct DirectLighting;
+
+impl Integrator for DirectLighting {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vec3 {
+        let result = match scene.trace(ray, 0.001, f32::MAX) {
+            Some(result) => result,
+            None => return scene.sample_background(&ray.direction),
+        };
+        let TraceResult { material, hit, .. } = &result;
+        let w0 = -ray.direction.normalize();
+        let direct = emitted(scene, &result, None) + direct_lighting(&w0, hit, material, scene);
+
+        let next = match material.bounce(&w0, hit) {
+            Some(next) => next,
+            None => return direct,
+        };
+        let bounce = if next.specular {
+            None
+        } else {
+            Some((hit.point, next.pdf))
+        };
+        let incident = match scene.trace(&next.ray, 0.001, f32::MAX) {
+            Some(light) => emitted(scene, &light, bounce),
+            None => escaped(scene, &next.ray.direction, bounce),
+        };
+        direct + next.weight.component_mul(&incident)
+    }
+}
@@ -0,0 +1,64 @@
+use rand::prelude::*;
+
+use super::*;
+
+/// Unidirectional path tracer with next-event estimation
+pub struct PathTracer {
+    /// Hard cap on path length, unbounded if not set
+    pub max_depth: Option<usize>,
+    /// Path length after which paths are terminated by russian roulette
+    pub roulette_depth: usize,
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, r: &Ray, scene: &Scene) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let mut radiance: Vec3 = glm::zero();
+        let mut throughput = glm::vec3(1.0, 1.0, 1.0);
+        let mut ray = r.clone();
+        let mut bounce: Option<(Vec3, f32)> = None;
+        let mut depth = 0;
+
+        while self.max_depth.is_none_or(|max| depth < max) {
+            let result = match scene.trace(&ray, 0.001, f32::MAX) {
+                Some(result) => result,
+                None => {
+                    let environment = if depth == 0 {
+                        scene.sample_background(&ray.direction)
+                    } else {
+                        escaped(scene, &ray.direction, bounce)
+                    };
+                    radiance += throughput.component_mul(&environment);
+                    break;
+                }
+            };
+            let TraceResult { material, hit, .. } = &result;
+
+            let w0 = -ray.direction.normalize();
+            radiance += throughput.component_mul(&emitted(scene, &result, bounce));
+            radiance += throughput.component_mul(&direct_lighting(&w0, hit, material, scene));
+
+            let next = match material.bounce(&w0, hit) {
+                Some(next) => next,
+                None => break,
+            };
+            throughput = throughput.component_mul(&next.weight);
+
+            depth += 1;
+            if depth >= self.roulette_depth {
+                let survival = f32::min(glm::comp_max(&throughput), 0.95);
+                if rng.gen::<f32>() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+            bounce = if next.specular {
+                None
+            } else {
+                Some((hit.point, next.pdf))
+            };
+            ray = next.ray;
+        }
+        radiance
+    }
+}
@@ -0,0 +1,17 @@
+pub mod camera;
+pub mod colorspace;
+pub mod config;
+pub mod environment;
+pub mod geom;
+pub mod integrator;
+pub mod material;
+mod obj;
+pub mod ray;
+pub mod render;
+pub mod texture;
+pub mod vec;
+
+pub use config::{RenderParams, UserConfig};
+pub use ray::Ray;
+pub use render::{render, Image};
+pub use vec::*;
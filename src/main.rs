@@ -1,21 +1,24 @@
 mod app;
-mod camera;
-mod config;
-mod geom;
-mod material;
-mod obj;
-mod ray;
+mod cli;
 mod style;
-mod texture;
-mod vec;
 
 use app::AppModel;
 
-use ray::Ray;
-use vec::*;
-
 use iced::{Application, Settings};
 
 pub fn main() {
-    AppModel::run(Settings::default());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        AppModel::run(Settings::default());
+    } else if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", cli::USAGE);
+    } else {
+        let result = cli::Options::parse(args)
+            .map_err(Into::into)
+            .and_then(cli::run);
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -1,32 +1,14 @@
 use nalgebra_glm as glm;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 use rand::prelude::*;
 
 use crate::geom::RayHit;
 use crate::ray::Ray;
-use crate::texture::{ColorTexture, GrayScaleTexture, Texture as _};
+use crate::texture::{ColorTexture, GrayScaleTexture, TexCoord, Texture as _};
+use crate::vec::{perpendicular, transform_to_world};
 use crate::{Vec2, Vec3};
 
-fn transform_to_world(vec: &Vec3, norm: &Vec3) -> Vec3 {
-    // Find an axis that is not parallel to normal
-    let major_axis = if f32::abs(norm.x) < (1.0 / f32::sqrt(3.0)) {
-        glm::vec3(1.0, 0.0, 0.0)
-    } else if f32::abs(norm.y) < (1.0 / f32::sqrt(3.0)) {
-        glm::vec3(0.0, 1.0, 0.0)
-    } else {
-        glm::vec3(0.0, 0.0, 1.0)
-    };
-
-    // Create a coordinate system relative to world space
-    let u = glm::normalize(&norm.cross(&major_axis));
-    let v = norm.cross(&u);
-    let w = norm;
-
-    // Transform from local coordinates to world coordinates
-    v * vec.x + w * vec.y + u * vec.z
-}
-
 #[derive(Deserialize, Clone)]
 pub struct Material {
     pub albedo: ColorTexture,
@@ -35,9 +17,107 @@ pub struct Material {
 
     #[serde(default)]
     pub emission: ColorTexture,
+    /// Fraction of light passing through the surface, tinted by `albedo`
+    #[serde(default)]
+    pub transmission: GrayScaleTexture,
+    #[serde(default = "default_ior")]
+    pub ior: f32,
+
+    /// Tangent-space normals, with blue pointing away from the surface. Read as raw data
+    /// unless a colorspace is given.
+    #[serde(default, deserialize_with = "normal_map")]
+    pub normal_map: Option<ColorTexture>,
+    /// Surface height, perturbing the normal by its slope
+    #[serde(default)]
+    pub bump_map: Option<GrayScaleTexture>,
+    /// Height of a full-white texel relative to the texel size
+    #[serde(default = "default_bump_scale")]
+    pub bump_scale: f32,
+}
+
+fn default_ior() -> f32 {
+    1.5
+}
+
+fn default_bump_scale() -> f32 {
+    1.0
+}
+
+fn normal_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ColorTexture>, D::Error> {
+    ColorTexture::deserialize_data(deserializer).map(Some)
+}
+
+pub struct Bounce {
+    pub ray: Ray,
+    /// Scattering function times cosine, divided by the pdf
+    pub weight: Vec3,
+    /// Density with respect to solid angle, or the probability of the chosen direction
+    /// for specular bounces
+    pub pdf: f32,
+    /// Whether the direction comes from a delta distribution that light sampling can't reach
+    pub specular: bool,
 }
 
 impl Material {
+    pub fn is_emissive(&self) -> bool {
+        !self.emission.is_black()
+    }
+
+    /// Converts the colors of the material to other primaries
+    pub fn transform_colors(&mut self, matrix: &glm::Mat3) {
+        self.albedo.transform(matrix);
+        self.emission.transform(matrix);
+    }
+
+    /// Shading normal of `hit` with the normal and bump maps applied, facing the same side
+    /// as `hit.shading_normal`
+    pub fn shading_normal(&self, hit: &RayHit) -> Vec3 {
+        if self.normal_map.is_none() && self.bump_map.is_none() {
+            return hit.shading_normal;
+        }
+        let RayHit { dpdu, dpdv, .. } = *hit;
+        let at = hit.tex_coord();
+        let mut normal = hit.outward_shading_normal();
+        let tangent = dpdu - normal * glm::dot(&normal, &dpdu);
+        let tangent = if glm::length(&tangent) > 1e-6 {
+            tangent.normalize()
+        } else {
+            perpendicular(&normal)
+        };
+        // Keep the handedness of the surface parametrization
+        let bitangent = if glm::dot(&normal.cross(&tangent), &dpdv) < 0.0 {
+            -normal.cross(&tangent)
+        } else {
+            normal.cross(&tangent)
+        };
+
+        if let Some(map) = &self.normal_map {
+            let local = map.sample(&at) * 2.0 - glm::vec3(1.0, 1.0, 1.0);
+            normal = glm::normalize(&(tangent * local.x + bitangent * local.y + normal * local.z));
+        }
+        if let Some(map) = &self.bump_map {
+            let texel = map.texel_size().unwrap_or_else(|| glm::vec2(1e-3, 1e-3));
+            let height = |offset: Vec2| {
+                let at = TexCoord {
+                    uv: at.uv + offset,
+                    ..at
+                };
+                map.sample(&at) * self.bump_scale
+            };
+            let du = height(glm::vec2(texel.x, 0.0)) - height(glm::vec2(-texel.x, 0.0));
+            let dv = height(glm::vec2(0.0, texel.y)) - height(glm::vec2(0.0, -texel.y));
+            normal = glm::normalize(&(normal - tangent * (0.5 * du) - bitangent * (0.5 * dv)));
+        }
+
+        if hit.front_face {
+            normal
+        } else {
+            -normal
+        }
+    }
+
     fn importance_theta(&self, roughness: f32) -> f32 {
         let mut rng = rand::thread_rng();
         let a = roughness * roughness;
@@ -46,38 +126,257 @@ impl Material {
         f32::atan(a * sqrt)
     }
 
-    pub fn bounce(&self, w0: &Vec3, hit: &RayHit) -> (Ray, f32) {
-        let n = hit.normal;
-        let mut rng = rand::thread_rng();
-        let roughness = self.roughness.sample(hit.uv);
-        let theta = self.importance_theta(roughness);
-        let phi: f32 = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+    fn roughness_at(&self, at: &TexCoord) -> f32 {
+        // A perfectly smooth surface would make the distribution degenerate
+        f32::max(self.roughness.sample(at), 0.001)
+    }
+
+    /// Probability of sampling the specular lobe rather than the diffuse one
+    fn specular_probability(&self, at: &TexCoord) -> f32 {
+        0.5 + 0.5 * self.metalness.sample(at)
+    }
+
+    /// Below this roughness glass is treated as perfectly smooth
+    fn is_smooth(&self, at: &TexCoord) -> bool {
+        self.roughness.sample(at) < 0.01
+    }
+
+    /// Ratio of the refractive index on the side that was hit to the one behind the surface
+    fn eta(&self, hit: &RayHit) -> f32 {
+        if hit.front_face {
+            1.0 / self.ior
+        } else {
+            self.ior
+        }
+    }
 
+    /// Microfacet normal distributed according to `normal_distribution` times its cosine
+    fn sample_microfacet(&self, n: &Vec3, roughness: f32) -> Vec3 {
+        let phi: f32 = rand::thread_rng().gen::<f32>() * 2.0 * std::f32::consts::PI;
+        let theta = self.importance_theta(roughness);
         let x = f32::sin(theta) * f32::sin(phi);
         let y = f32::cos(theta);
         let z = f32::sin(theta) * f32::cos(phi);
+        glm::normalize(&transform_to_world(&glm::vec3(x, y, z), n))
+    }
 
-        let direction = glm::normalize(&transform_to_world(&glm::vec3(x, y, z), &n));
-        let h = glm::normalize(&(w0 + direction));
+    pub fn bounce(&self, w0: &Vec3, hit: &RayHit) -> Option<Bounce> {
+        let n = facing_normal(w0, hit);
+        let at = hit.tex_coord();
+        let mut rng = rand::thread_rng();
 
-        let cost = f32::max(0.0, glm::dot(&n, &h));
-        let pdf = normal_distribution(&n, &h, roughness) * cost;
-        let p = pdf / (4.0 * f32::max(0.0, glm::dot(&w0, &h)));
-        (Ray::new(hit.point, direction), p)
+        let direction = if rng.gen::<f32>() < self.transmission.sample(&at) {
+            if self.is_smooth(&at) {
+                return self.bounce_dielectric(w0, hit);
+            }
+            let h = self.sample_microfacet(&n, self.roughness_at(&at));
+            let cos_h = glm::dot(w0, &h);
+            if cos_h <= 0.0 {
+                return None;
+            }
+            let eta = self.eta(hit);
+            let reflect = rng.gen::<f32>() < fresnel_dielectric(cos_h, eta);
+            let direction = if reflect {
+                2.0 * cos_h * h - w0
+            } else {
+                refract(w0, &h, eta)
+            };
+            // Steep facets can send either lobe to the other side of the surface, where
+            // `pdf` and `eval` would take it for the other one
+            if (glm::dot(&n, &direction) > 0.0) != reflect {
+                return None;
+            }
+            direction
+        } else if rng.gen::<f32>() < self.specular_probability(&at) {
+            let h = self.sample_microfacet(&n, self.roughness_at(&at));
+            2.0 * glm::dot(w0, &h) * h - w0
+        } else {
+            let phi: f32 = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+            let r2: f32 = rng.gen();
+            let r = f32::sqrt(r2);
+            let local = glm::vec3(r * f32::sin(phi), f32::sqrt(1.0 - r2), r * f32::cos(phi));
+            transform_to_world(&local, &n)
+        };
+        let direction = glm::normalize(&direction);
+        let pdf = self.pdf(w0, &direction, hit);
+        let costheta = f32::abs(glm::dot(&hit.shading_normal, &direction));
+        let weight = self.eval(w0, &direction, hit) * costheta / pdf;
+        if pdf <= 0.0 || weight == glm::zero::<Vec3>() {
+            return None;
+        }
+        Some(Bounce {
+            ray: Ray::new(hit.point, direction),
+            weight,
+            pdf,
+            specular: false,
+        })
+    }
+
+    /// Smooth glass: Fresnel-weighted choice between mirror reflection and refraction
+    fn bounce_dielectric(&self, w0: &Vec3, hit: &RayHit) -> Option<Bounce> {
+        let n = facing_normal(w0, hit);
+        let eta = self.eta(hit);
+        let cos_i = f32::min(glm::dot(&n, w0), 1.0);
+        let reflectance = fresnel_dielectric(cos_i, eta);
+        let (direction, weight, pdf) = if rand::thread_rng().gen::<f32>() < reflectance {
+            let direction = 2.0 * cos_i * n - w0;
+            (direction, glm::vec3(1.0, 1.0, 1.0), reflectance)
+        } else {
+            let direction = refract(w0, &n, eta).normalize();
+            (
+                direction,
+                self.albedo.sample(&hit.tex_coord()),
+                1.0 - reflectance,
+            )
+        };
+        // The shading normal may send the ray to the wrong side of the actual surface
+        let reflected = glm::dot(&n, &direction) > 0.0;
+        if reflected != (glm::dot(&hit.geometric_normal, &direction) > 0.0) {
+            return None;
+        }
+        Some(Bounce {
+            ray: Ray::new(hit.point, direction),
+            weight,
+            pdf,
+            specular: true,
+        })
+    }
+
+    /// Density with which `bounce` picks `wi`, with respect to solid angle
+    pub fn pdf(&self, w0: &Vec3, wi: &Vec3, hit: &RayHit) -> f32 {
+        let n = facing_normal(w0, hit);
+        let at = hit.tex_coord();
+        let transmission = self.transmission.sample(&at);
+        let rough_glass = if transmission > 0.0 && !self.is_smooth(&at) {
+            transmission * self.pdf_dielectric(w0, wi, hit)
+        } else {
+            0.0
+        };
+        let costheta = glm::dot(&n, wi);
+        if costheta <= 0.0 {
+            return rough_glass;
+        }
+        let h = glm::normalize(&(w0 + wi));
+        let cosh = f32::max(0.0, glm::dot(&n, &h));
+        let specular = normal_distribution(&n, &h, self.roughness_at(&at)) * cosh
+            / (4.0 * f32::abs(glm::dot(w0, &h)));
+        let diffuse = costheta / std::f32::consts::PI;
+        let p = self.specular_probability(&at);
+        (1.0 - transmission) * (p * specular + (1.0 - p) * diffuse) + rough_glass
     }
 
     /// Return type is (brdf, fresnel)
-    pub fn brdf(&self, w0: &Vec3, wi: &Vec3, n: &Vec3, uv: Vec2) -> (Vec3, Vec3) {
+    pub fn brdf(&self, w0: &Vec3, wi: &Vec3, n: &Vec3, at: &TexCoord) -> (Vec3, Vec3) {
         let h = glm::normalize(&(w0 + wi));
-        let d = normal_distribution(&n, &h, self.roughness.sample(uv));
+        let d = normal_distribution(&n, &h, self.roughness_at(at));
         let f0 = glm::vec3(0.04, 0.04, 0.04);
-        let f0 = glm::mix(&f0, &self.albedo.sample(uv), self.metalness.sample(uv));
+        let f0 = glm::mix(&f0, &self.albedo.sample(at), self.metalness.sample(at));
         let f = fresnel(&wi, &h, &f0);
         let g = geometry(&n, &h, w0, wi);
         let num = d * f * g;
         let denom = 4.0 * glm::dot(&n, &wi) * glm::dot(&n, &w0);
         (num / denom, f)
     }
+
+    /// Full BSDF value for the pair of directions, diffuse lobe included. Smooth
+    /// transmission is a delta distribution and does not contribute.
+    pub fn eval(&self, w0: &Vec3, wi: &Vec3, hit: &RayHit) -> Vec3 {
+        let n = facing_normal(w0, hit);
+        let at = hit.tex_coord();
+        let transmission = self.transmission.sample(&at);
+        let rough_glass = if transmission > 0.0 && !self.is_smooth(&at) {
+            self.eval_dielectric(w0, wi, hit) * transmission
+        } else {
+            glm::zero()
+        };
+        // Reflection needs both directions above the shading and the actual surface,
+        // otherwise light leaks through or is blocked by the surface itself
+        if glm::dot(&n, wi) <= 0.0 || glm::dot(&hit.geometric_normal, wi) <= 0.0 {
+            return rough_glass;
+        }
+        let (specular, ks) = self.brdf(w0, wi, &n, &at);
+        let lambert = self.albedo.sample(&at) / std::f32::consts::PI;
+        let kd = (glm::vec3(1.0, 1.0, 1.0) - ks) * (1.0 - self.metalness.sample(&at));
+        (kd.component_mul(&lambert) + specular) * (1.0 - transmission) + rough_glass
+    }
+
+    /// Microfacet normal of a rough dielectric scattering `w0` into `wi`, oriented to the side
+    /// of the shading normal. `None` if no facet can connect the two directions.
+    fn dielectric_half_vector(&self, w0: &Vec3, wi: &Vec3, hit: &RayHit) -> Option<Vec3> {
+        let n = facing_normal(w0, hit);
+        let cos_i = glm::dot(&n, wi);
+        let reflected = glm::dot(&hit.geometric_normal, wi) > 0.0;
+        if cos_i == 0.0 || reflected != (cos_i > 0.0) {
+            return None;
+        }
+        let h = if reflected {
+            w0 + wi
+        } else {
+            -(self.eta(hit) * w0 + wi)
+        };
+        let h = glm::normalize(&h);
+        let h = if glm::dot(&n, &h) < 0.0 { -h } else { h };
+        // Refraction has to enter the facet from the front and leave through its back
+        if glm::dot(w0, &h) <= 0.0 || glm::dot(wi, &h) * cos_i <= 0.0 {
+            return None;
+        }
+        Some(h)
+    }
+
+    /// Walter et al. 2007, "Microfacet Models for Refraction through Rough Surfaces"
+    fn eval_dielectric(&self, w0: &Vec3, wi: &Vec3, hit: &RayHit) -> Vec3 {
+        let h = match self.dielectric_half_vector(w0, wi, hit) {
+            Some(h) => h,
+            None => return glm::zero(),
+        };
+        let n = facing_normal(w0, hit);
+        let at = hit.tex_coord();
+        let roughness = self.roughness_at(&at);
+        let (cos_o, cos_i) = (glm::dot(&n, w0), glm::dot(&n, wi));
+        let (o_h, i_h) = (glm::dot(w0, &h), glm::dot(wi, &h));
+        let eta = self.eta(hit);
+        let f = fresnel_dielectric(o_h, eta);
+        let d = normal_distribution(&n, &h, roughness);
+        let g = smith_g1(&n, &h, w0, roughness) * smith_g1(&n, &h, wi, roughness);
+        if cos_i > 0.0 {
+            glm::vec3(1.0, 1.0, 1.0) * (f * d * g / (4.0 * cos_o * cos_i))
+        } else {
+            let denom = eta * o_h + i_h;
+            let t = (1.0 - f) * d * g * o_h * f32::abs(i_h) / (cos_o * -cos_i * denom * denom);
+            self.albedo.sample(&at) * t
+        }
+    }
+
+    fn pdf_dielectric(&self, w0: &Vec3, wi: &Vec3, hit: &RayHit) -> f32 {
+        let h = match self.dielectric_half_vector(w0, wi, hit) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let n = facing_normal(w0, hit);
+        let (o_h, i_h) = (glm::dot(w0, &h), glm::dot(wi, &h));
+        let eta = self.eta(hit);
+        let f = fresnel_dielectric(o_h, eta);
+        let facet_pdf =
+            normal_distribution(&n, &h, self.roughness_at(&hit.tex_coord())) * glm::dot(&n, &h);
+        if glm::dot(&n, wi) > 0.0 {
+            f * facet_pdf / (4.0 * o_h)
+        } else {
+            let denom = eta * o_h + i_h;
+            (1.0 - f) * facet_pdf * f32::abs(i_h) / (denom * denom)
+        }
+    }
+}
+
+/// Shading normal of `hit`, bent towards `w0` where the shading normal would hide it
+fn facing_normal(w0: &Vec3, hit: &RayHit) -> Vec3 {
+    const MIN_COS: f32 = 0.01;
+    let n = hit.shading_normal;
+    let cos = glm::dot(&n, w0);
+    if cos >= MIN_COS {
+        n
+    } else {
+        glm::normalize(&(n + w0 * (MIN_COS - cos)))
+    }
 }
 
 fn normal_distribution(n: &Vec3, h: &Vec3, roughness: f32) -> f32 {
@@ -101,3 +400,84 @@ fn geometry(n: &Vec3, h: &Vec3, w0: &Vec3, wi: &Vec3) -> f32 {
     let term3 = 2.0 * ndoth * f32::max(0.0, glm::dot(n, wi)) / w0doth;
     f32::min(1.0, f32::min(term2, term3))
 }
+
+/// Smith masking term of the GGX distribution for a single direction
+fn smith_g1(n: &Vec3, h: &Vec3, v: &Vec3, roughness: f32) -> f32 {
+    let cos = glm::dot(n, v);
+    if glm::dot(v, h) * cos <= 0.0 {
+        return 0.0;
+    }
+    let a2 = f32::powi(roughness * roughness, 2);
+    let cos = f32::abs(cos);
+    2.0 * cos / (cos + f32::sqrt(a2 + (1.0 - a2) * cos * cos))
+}
+
+/// Direction of `w0` refracted through a surface with normal `n`, `eta` as in
+/// `fresnel_dielectric`. Total internal reflection must have been ruled out.
+fn refract(w0: &Vec3, n: &Vec3, eta: f32) -> Vec3 {
+    let cos_i = glm::dot(n, w0);
+    let cos_t = f32::sqrt(f32::max(0.0, 1.0 - eta * eta * (1.0 - cos_i * cos_i)));
+    -eta * w0 + (eta * cos_i - cos_t) * n
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, `eta` being the ratio of the
+/// refractive index on the incident side to the one on the transmitted side
+fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+    let rs = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let rp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Average weight of bounces off rough glass lit from all around by a constant
+    /// environment of 1, which can't exceed 1 for a white material
+    fn furnace(roughness: f32, cos_o: f32, front_face: bool) -> f32 {
+        let material: Material = toml::from_str(&format!(
+            "albedo = [1, 1, 1]\nmetalness = 0\nroughness = {}\ntransmission = 1",
+            roughness
+        ))
+        .unwrap();
+        let side = if front_face { 1.0 } else { -1.0 };
+        let hit = RayHit::new(
+            1.0,
+            glm::zero(),
+            glm::vec3(0.0, 1.0, 0.0),
+            glm::vec2(0.5, 0.5),
+            front_face,
+        );
+        let w0 = glm::vec3(f32::sqrt(1.0 - cos_o * cos_o), cos_o * side, 0.0);
+        let samples = 10_000;
+        let total: f32 = (0..samples)
+            .filter_map(|_| material.bounce(&w0, &hit))
+            .map(|bounce| bounce.weight.x)
+            .sum();
+        total / samples as f32
+    }
+
+    #[test]
+    fn rough_glass_does_not_add_energy() {
+        for &roughness in &[0.15, 0.3] {
+            for &cos_o in &[1.0, 0.7, 0.4] {
+                for &front_face in &[true, false] {
+                    let albedo = furnace(roughness, cos_o, front_face);
+                    assert!(
+                        albedo <= 1.01,
+                        "roughness {}, cos {}, front {}: {}",
+                        roughness,
+                        cos_o,
+                        front_face,
+                        albedo
+                    );
+                }
+            }
+        }
+    }
+}
@@ -2,10 +2,21 @@ use nalgebra_glm as glm;
 
 type Vec3 = glm::TVec3<f32>;
 
+#[derive(Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
     pub inv_dir: Vec3,
+    pub differentials: Option<Differentials>,
+}
+
+/// Rays through the neighbouring pixels in x and y, used to estimate texture footprints
+#[derive(Clone, Copy)]
+pub struct Differentials {
+    pub rx_origin: Vec3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_direction: Vec3,
 }
 
 impl Ray {
@@ -15,6 +26,14 @@ impl Ray {
             origin,
             direction,
             inv_dir,
+            differentials: None,
+        }
+    }
+
+    pub fn with_differentials(self, differentials: Differentials) -> Self {
+        Ray {
+            differentials: Some(differentials),
+            ..self
         }
     }
 
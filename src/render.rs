@@ -0,0 +1,53 @@
+use rand::prelude::*;
+use rayon::prelude::*;
+
+use crate::colorspace::Transfer;
+use crate::config::UserConfig;
+use crate::integrator::{self, Output};
+use crate::vec::*;
+
+pub type Image = image::RgbImage;
+
+/// Renders `config`, failing if it selects a camera that doesn't exist
+pub fn render(config: &UserConfig) -> Result<Image, String> {
+    let UserConfig { params, scene, .. } = config;
+    let w = params.resolution.x;
+    let h = params.resolution.y;
+    let camera = config.camera()?.build(params.resolution);
+    let integrator = integrator::build(params);
+
+    let buffer = (0..w * h)
+        .into_par_iter()
+        .flat_map(|i| {
+            let x = i % w;
+            let y = i / w;
+            let color = (0..params.samples)
+                .into_par_iter()
+                .map(|_| {
+                    let mut rng = rand::thread_rng();
+                    let rand: f32 = rng.gen();
+                    let u = (x as f32 + rand) / w as f32;
+                    let rand: f32 = rng.gen();
+                    let v = (y as f32 + rand) / h as f32;
+                    camera
+                        .ray_at(u, v)
+                        .map_or_else(glm::zero, |ray| integrator.radiance(&ray, scene))
+                })
+                .sum::<Vec3>()
+                / params.samples as f32;
+            let (color, transfer) = match integrator.output() {
+                Output::Radiance => {
+                    let color = params.working_space.to_rec709(color);
+                    let color = glm::vec3(1.0, 1.0, 1.0) - glm::exp(&(-color * params.exposure));
+                    (color, params.transfer)
+                }
+                Output::Color => (params.working_space.to_rec709(color), params.transfer),
+                Output::Raw => (color, Transfer::Linear),
+            };
+            let encode = |c: f32| (transfer.encode(c.clamp(0.0, 1.0)) * 255.99) as u8;
+            vec![encode(color.x), encode(color.y), encode(color.z)]
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Image::from_raw(w, h, buffer).expect("Buffer size does not match resolution"))
+}
@@ -1,32 +1,270 @@
 mod color;
+mod file;
 mod grayscale;
+mod mipmap;
+mod node;
+mod procedural;
 
 use std::ops::*;
 
-use crate::Vec2;
+use serde::de::{self, value::MapAccessDeserializer, DeserializeOwned, MapAccess};
+use serde::Deserialize;
+use toml::value::{Table, Value};
+
+use crate::colorspace::ColorSpace;
+use crate::{Vec2, Vec3};
 use nalgebra_glm as glm;
 
 pub use color::*;
 pub use grayscale::*;
+pub use mipmap::*;
+pub use node::*;
+pub use procedural::*;
+
+/// Surface location a texture is evaluated at
+#[derive(Clone, Copy, Debug)]
+pub struct TexCoord {
+    pub uv: Vec2,
+    /// World space position, or the direction for environment lookups
+    pub point: Vec3,
+    /// Change of uv to the neighbouring pixels, selecting the mip level
+    pub duvdx: Vec2,
+    pub duvdy: Vec2,
+}
+
+impl TexCoord {
+    /// Point sampled at full resolution
+    pub fn new(uv: Vec2, point: Vec3) -> Self {
+        TexCoord {
+            uv,
+            point,
+            duvdx: glm::zero(),
+            duvdy: glm::zero(),
+        }
+    }
+}
+
+/// How coordinates outside the image are brought back into it
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    fn apply(self, i: i64, size: u32) -> u32 {
+        let size = i64::from(size.max(1));
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Clamp => i.clamp(0, size - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as u32
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+    Bicubic,
+}
+
+/// Mapping from surface uvs to texture coordinates: scaled, then rotated counter-clockwise
+/// about the origin, then offset
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct UvTransform {
+    pub scale: Vec2,
+    pub offset: Vec2,
+    /// In degrees
+    pub rotation: f32,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform {
+            scale: glm::vec2(1.0, 1.0),
+            offset: glm::zero(),
+            rotation: 0.0,
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn apply(&self, uv: Vec2) -> Vec2 {
+        self.linear(uv) + self.offset
+    }
+
+    /// Scale and rotation only, for mapping uv differences
+    pub fn linear(&self, uv: Vec2) -> Vec2 {
+        let uv = uv.component_mul(&self.scale);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        glm::vec2(cos * uv.x - sin * uv.y, sin * uv.x + cos * uv.y)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sampler {
+    #[serde(default)]
+    pub wrap: Wrap,
+    #[serde(default)]
+    pub filter: Filter,
+    #[serde(flatten)]
+    pub transform: UvTransform,
+}
+
+/// Channel of an image file read into a grayscale texture, e.g. `"g"` for the roughness of a
+/// packed occlusion/roughness/metalness map
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Rec. 709 weighted sum of red, green and blue
+    #[default]
+    Luma,
+    R,
+    G,
+    B,
+    A,
+}
+
+impl Channel {
+    pub fn select(self, [r, g, b, a]: [f32; 4]) -> f32 {
+        match self {
+            Channel::Luma => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+            Channel::R => r,
+            Channel::G => g,
+            Channel::B => b,
+            Channel::A => a,
+        }
+    }
+}
+
+/// Image texture given as a table, e.g. `{ path = "wood.png", wrap = "mirror", scale = [4, 4] }`
+#[derive(Deserialize)]
+struct ImageDesc {
+    path: String,
+    /// Only used by grayscale textures
+    #[serde(default)]
+    channel: Channel,
+    /// Defaults to sRGB for integer files and linear for float files, or raw for normal maps
+    colorspace: Option<ColorSpace>,
+    #[serde(flatten)]
+    sampler: Sampler,
+}
+
+/// Texture given as a table: an image file, a procedural pattern or an operation on
+/// other textures, told apart by their keys
+enum TableDesc<P, T> {
+    Image(ImageDesc),
+    Procedural(Procedural<P>),
+    Node(Node<T>),
+}
+
+impl<P: DeserializeOwned, T: Input> TableDesc<P, T> {
+    /// Reads a table, with files of node inputs read as `space` unless they give one
+    fn from_map<'de, A: MapAccess<'de>>(
+        map: A,
+        space: Option<ColorSpace>,
+    ) -> Result<Self, A::Error> {
+        // Read whole first, so that the error of the kind of table it turns out to be, such
+        // as a missing file, isn't lost trying the others
+        let table = Table::deserialize(MapAccessDeserializer::new(map))?;
+        let desc = if table.contains_key("path") {
+            Value::Table(table).try_into().map(TableDesc::Image)
+        } else if table.contains_key("pattern") {
+            Value::Table(table).try_into().map(TableDesc::Procedural)
+        } else {
+            Node::from_table(table, space).map(TableDesc::Node)
+        };
+        desc.map_err(de::Error::custom)
+    }
+}
 
 pub trait Texture {
+    type Pixel;
+
+    fn sample(&self, at: &TexCoord) -> Self::Pixel;
+}
+
+/// Image data looked up by uv through a `Sampler`
+pub trait Raster {
     type Pixel: Mul<f32, Output = Self::Pixel> + Add<Self::Pixel, Output = Self::Pixel>;
 
     fn dimensions(&self) -> Vec2;
 
     fn pixel_at(&self, x: u32, y: u32) -> Self::Pixel;
 
-    fn sample(&self, uv: Vec2) -> Self::Pixel {
+    fn sampler(&self) -> Sampler {
+        Sampler::default()
+    }
+
+    /// Pixel at any integer coordinates, resolved through the wrap mode
+    fn texel(&self, x: i64, y: i64) -> Self::Pixel {
         let dim = self.dimensions();
-        let point = uv.component_mul(&(dim - glm::vec2(1.0, 1.0)));
-        let (p1, p2) = (glm::floor(&point), glm::ceil(&point));
-        let t = point - p1;
-        let f11 = self.pixel_at(p1.x as u32, p1.y as u32);
-        let f21 = self.pixel_at(p2.x as u32, p1.y as u32);
-        let f12 = self.pixel_at(p1.x as u32, p2.y as u32);
-        let f22 = self.pixel_at(p2.x as u32, p2.y as u32);
-        let a = f11 * (1.0 - t.x) + f21 * t.x;
-        let b = f12 * (1.0 - t.x) + f22 * t.x;
-        a * (1.0 - t.y) + b * t.y
+        let wrap = self.sampler().wrap;
+        self.pixel_at(wrap.apply(x, dim.x as u32), wrap.apply(y, dim.y as u32))
+    }
+
+    fn lookup(&self, uv: Vec2) -> Self::Pixel {
+        let sampler = self.sampler();
+        // Pixel centers lie at half-integer coordinates
+        let point = sampler
+            .transform
+            .apply(uv)
+            .component_mul(&self.dimensions())
+            // NaN or infinite uvs, e.g. from degenerate triangles, would poison the filter weights
+            .map(|c| if c.is_finite() { c } else { 0.0 });
+        match sampler.filter {
+            Filter::Nearest => self.texel(point.x.floor() as i64, point.y.floor() as i64),
+            Filter::Bilinear => {
+                let point = point - glm::vec2(0.5, 0.5);
+                let p1 = glm::floor(&point);
+                let t = point - p1;
+                let (x, y) = (p1.x as i64, p1.y as i64);
+                let (x1, y1) = (x.wrapping_add(1), y.wrapping_add(1));
+                let a = self.texel(x, y) * (1.0 - t.x) + self.texel(x1, y) * t.x;
+                let b = self.texel(x, y1) * (1.0 - t.x) + self.texel(x1, y1) * t.x;
+                a * (1.0 - t.y) + b * t.y
+            }
+            Filter::Bicubic => {
+                let point = point - glm::vec2(0.5, 0.5);
+                let p1 = glm::floor(&point);
+                let t = point - p1;
+                let (x, y) = (p1.x as i64, p1.y as i64);
+                let (wx, wy) = (catmull_rom(t.x), catmull_rom(t.y));
+                let row = |j: i64| {
+                    let y = y.wrapping_add(j);
+                    self.texel(x.wrapping_sub(1), y) * wx[0]
+                        + self.texel(x, y) * wx[1]
+                        + self.texel(x.wrapping_add(1), y) * wx[2]
+                        + self.texel(x.wrapping_add(2), y) * wx[3]
+                };
+                row(-1) * wy[0] + row(0) * wy[1] + row(1) * wy[2] + row(2) * wy[3]
+            }
+        }
     }
 }
+
+/// Weights of the four pixels around a point `t` past the second one
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
@@ -1,30 +1,73 @@
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
+use std::fmt;
 use std::path::Path;
 
-use image::{self, hdr::HDRDecoder};
+use serde::de::value::SeqAccessDeserializer;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 
-use serde::{de::Visitor, Deserialize, Deserializer};
-
-use super::Texture;
+use super::{file, Input, MipMap, Node, Procedural, Sampler, TableDesc, TexCoord, Texture};
+use crate::colorspace::ColorSpace;
 
 use crate::{Vec2, Vec3};
 use nalgebra_glm as glm;
 
 #[derive(Clone)]
-pub struct ColorTexture {
-    buf: Vec<Vec3>,
-    width: u32,
-    height: u32,
+pub enum ColorTexture {
+    Solid(Vec3),
+    Image(MipMap<Vec3>, ColorSpace),
+    Procedural(Procedural<Vec3>),
+    Node(Box<Node<ColorTexture>>),
 }
 
 impl ColorTexture {
     pub fn solid(color: Vec3) -> Self {
-        ColorTexture {
-            buf: vec![color],
-            width: 1,
-            height: 1,
+        ColorTexture::Solid(color)
+    }
+
+    pub fn is_black(&self) -> bool {
+        let black = |c: &Vec3| *c == glm::zero::<Vec3>();
+        match self {
+            ColorTexture::Solid(color) => black(color),
+            ColorTexture::Image(img, _) => img.pixels().iter().all(black),
+            ColorTexture::Procedural(p) => p.values.iter().all(black),
+            ColorTexture::Node(_) => false,
+        }
+    }
+
+    /// Size of a pixel in uv units, for textures that have pixels
+    pub fn texel_size(&self) -> Option<Vec2> {
+        match self {
+            ColorTexture::Image(img, _) => {
+                Some(glm::vec2(1.0, 1.0).component_div(&img.dimensions()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Applies a change of primaries to all colors, leaving raw data untouched
+    pub fn transform(&mut self, matrix: &glm::Mat3) {
+        match self {
+            ColorTexture::Solid(color) => *color = matrix * *color,
+            ColorTexture::Image(img, space) => {
+                if *space != ColorSpace::Raw {
+                    img.map(|c| matrix * c);
+                }
+            }
+            ColorTexture::Procedural(p) => {
+                for value in &mut p.values {
+                    *value = matrix * *value;
+                }
+            }
+            ColorTexture::Node(node) => match node.as_mut() {
+                Node::Mix { mix: (a, b, _) } | Node::Multiply { multiply: (a, b) } => {
+                    a.transform(matrix);
+                    b.transform(matrix);
+                }
+                Node::Remap { remap: input, .. } | Node::Invert { invert: input } => {
+                    input.transform(matrix)
+                }
+            },
         }
     }
 }
@@ -38,78 +81,87 @@ impl Default for ColorTexture {
 impl Texture for ColorTexture {
     type Pixel = Vec3;
 
-    fn dimensions(&self) -> Vec2 {
-        glm::vec2(self.width as f32, self.height as f32)
+    fn sample(&self, at: &TexCoord) -> Self::Pixel {
+        match self {
+            ColorTexture::Solid(color) => *color,
+            ColorTexture::Image(img, _) => img.sample(at),
+            ColorTexture::Procedural(p) => p.sample(at),
+            ColorTexture::Node(node) => node.sample(at),
+        }
     }
+}
 
-    fn pixel_at(&self, x: u32, y: u32) -> Self::Pixel {
-        let idx = (y * self.width + x) as usize;
-        self.buf[idx]
-    }
+/// Reads an image file, converting it from `space` or the file's default space to linear
+fn open<'a, P: AsRef<Path>>(
+    path: P,
+    space: Option<ColorSpace>,
+    sampler: Sampler,
+) -> Result<ColorTexture, Box<dyn Error + 'a>> {
+    let decoded = file::read(path)?;
+    let space = space.unwrap_or_else(|| decoded.default_space());
+    let buf = decoded
+        .pixels
+        .into_iter()
+        .map(|[r, g, b, _]| Vec3::new(space.decode(r), space.decode(g), space.decode(b)))
+        .collect();
+    let img = MipMap::new(buf, decoded.width, decoded.height, sampler);
+    Ok(ColorTexture::Image(img, space))
 }
 
-fn open<'a, P: AsRef<Path>>(path: P) -> Result<ColorTexture, Box<dyn Error + 'a>> {
-    use std::ffi::OsStr;
-    if let Some("hdr") = path.as_ref().extension().and_then(OsStr::to_str) {
-        open_hdr(path)
-    } else {
-        let img = image::open(path)?.to_rgb();
-        let (width, height) = img.dimensions();
-        let buf = img.pixels().map(|p| rgb_to_float(*p)).collect();
-        Ok(ColorTexture { buf, width, height })
+impl<'de> Deserialize<'de> for ColorTexture {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TexVisitor { space: None })
     }
 }
 
-fn open_hdr<'a, P: AsRef<Path>>(path: P) -> Result<ColorTexture, Box<dyn Error + 'a>> {
-    let f = File::open(path)?;
-    let reader = BufReader::new(f);
-    let decoder = HDRDecoder::new(reader)?;
-    let metadata = decoder.metadata();
-    let width = metadata.width;
-    let height = metadata.height;
-    let buf = decoder
-        .read_image_hdr()?
-        .into_iter()
-        .map(|pix| glm::make_vec3(&pix.0))
-        .collect();
-    Ok(ColorTexture { width, height, buf })
+impl ColorTexture {
+    /// Deserializes a texture holding data rather than colors, with files read as raw
+    /// unless a colorspace is given
+    pub fn deserialize_data<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TexVisitor {
+            space: Some(ColorSpace::Raw),
+        })
+    }
 }
 
-fn rgb_to_float(pix: image::Rgb<u8>) -> Vec3 {
-    let [r, g, b] = pix.0;
-    let vec = Vec3::new(
-        f32::from(r) / 255.0,
-        f32::from(g) / 255.0,
-        f32::from(b) as f32 / 255.0,
-    );
-    glm::pow(&vec, &glm::vec3(2.2, 2.2, 2.2))
+impl Input for ColorTexture {
+    fn read(value: toml::Value, space: Option<ColorSpace>) -> Result<Self, toml::de::Error> {
+        value.deserialize_any(TexVisitor { space })
+    }
 }
 
-impl<'de> Deserialize<'de> for ColorTexture {
-    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        use serde::de::{value::SeqAccessDeserializer, Error, SeqAccess};
-        use std::fmt;
+struct TexVisitor {
+    /// Space of files that don't specify one, instead of the file type's default
+    space: Option<ColorSpace>,
+}
 
-        struct TexVisitor;
+impl<'de> Visitor<'de> for TexVisitor {
+    type Value = ColorTexture;
 
-        impl<'de> Visitor<'de> for TexVisitor {
-            type Value = ColorTexture;
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("path to color image file, texture table or array")
+    }
 
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("path to color image file or array")
-            }
+    // Load from texture file
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        open(value, self.space, Sampler::default()).map_err(E::custom)
+    }
 
-            // Load from texture file
-            fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
-                open(value).map_err(E::custom)
-            }
+    // Solid color
+    fn visit_seq<A: SeqAccess<'de>>(self, value: A) -> Result<Self::Value, A::Error> {
+        let color: Vec3 = Deserialize::deserialize(SeqAccessDeserializer::new(value))?;
+        Ok(ColorTexture::solid(color))
+    }
 
-            // Solid color
-            fn visit_seq<A: SeqAccess<'de>>(self, value: A) -> Result<Self::Value, A::Error> {
-                let color: Vec3 = Deserialize::deserialize(SeqAccessDeserializer::new(value))?;
-                Ok(ColorTexture::solid(color))
+    // Texture file with sampling options, procedural pattern or node
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        match TableDesc::<Vec3, ColorTexture>::from_map(map, self.space)? {
+            TableDesc::Image(desc) => {
+                let space = desc.colorspace.or(self.space);
+                open(&desc.path, space, desc.sampler).map_err(de::Error::custom)
             }
+            TableDesc::Procedural(p) => Ok(ColorTexture::Procedural(p)),
+            TableDesc::Node(node) => Ok(ColorTexture::Node(Box::new(node))),
         }
-        deserializer.deserialize_any(TexVisitor)
     }
 }
@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::hdr::HDRDecoder;
+
+use crate::colorspace::ColorSpace;
+
+/// Image file decoded to floating point RGBA, integer formats scaled to 0..1 without
+/// any transfer function applied
+pub struct Decoded {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+    /// Whether the file stores floating point values, which are linear by convention
+    pub float: bool,
+}
+
+impl Decoded {
+    /// The space of the stored values when none is given explicitly
+    pub fn default_space(&self) -> ColorSpace {
+        if self.float {
+            ColorSpace::Linear
+        } else {
+            ColorSpace::Srgb
+        }
+    }
+}
+
+/// Reads an image file, with errors naming the file
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Decoded, Box<dyn Error>> {
+    let path = path.as_ref();
+    decode(path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+fn decode(path: &Path) -> Result<Decoded, Box<dyn Error>> {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("exr") => read_exr(path),
+        Some("hdr") => read_hdr(path),
+        Some("png") => read_png(path),
+        _ => {
+            let img = image::open(path)?.to_rgba();
+            let (width, height) = img.dimensions();
+            let unorm = |c: u8| f32::from(c) / 255.0;
+            let pixels = img
+                .pixels()
+                .map(|p| {
+                    let [r, g, b, a] = p.0;
+                    [unorm(r), unorm(g), unorm(b), unorm(a)]
+                })
+                .collect();
+            Ok(Decoded {
+                width,
+                height,
+                pixels,
+                float: false,
+            })
+        }
+    }
+}
+
+/// PNGs are read through the `png` crate directly, as `image` truncates 16-bit files to 8 bits
+fn read_png(path: &Path) -> Result<Decoded, Box<dyn Error>> {
+    use png::{BitDepth, ColorType, Decoder, Transformations};
+
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buf)?;
+
+    let (color_type, depth) = reader.output_color_type();
+    let samples: Vec<f32> = match depth {
+        BitDepth::Sixteen => buf
+            .chunks_exact(2)
+            .map(|b| f32::from(u16::from_be_bytes([b[0], b[1]])) / 65535.0)
+            .collect(),
+        _ => buf.iter().map(|&b| f32::from(b) / 255.0).collect(),
+    };
+    let pixels = samples
+        .chunks_exact(color_type.samples())
+        .map(|s| match color_type {
+            ColorType::Grayscale => [s[0], s[0], s[0], 1.0],
+            ColorType::GrayscaleAlpha => [s[0], s[0], s[0], s[1]],
+            ColorType::RGB => [s[0], s[1], s[2], 1.0],
+            _ => [s[0], s[1], s[2], s[3]],
+        })
+        .collect();
+    Ok(Decoded {
+        width: info.width,
+        height: info.height,
+        pixels,
+        float: false,
+    })
+}
+
+fn read_hdr(path: &Path) -> Result<Decoded, Box<dyn Error>> {
+    let decoder = HDRDecoder::new(BufReader::new(File::open(path)?))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()?
+        .into_iter()
+        .map(|pix| {
+            let [r, g, b] = pix.0;
+            [r, g, b, 1.0]
+        })
+        .collect();
+    Ok(Decoded {
+        width: metadata.width,
+        height: metadata.height,
+        pixels,
+        float: true,
+    })
+}
+
+/// First layer of an OpenEXR file. Channels are matched by the last part of their name, so
+/// `diffuse.R` is read as red; luminance-only files store `Y`.
+fn read_exr(path: &Path) -> Result<Decoded, Box<dyn Error>> {
+    let image = exr::prelude::read_first_flat_layer_from_file(path)?;
+    let layer = image.layer_data;
+    let (width, height) = (layer.size.width(), layer.size.height());
+
+    let mut pixels = vec![[0.0, 0.0, 0.0, 1.0]; width * height];
+    for channel in layer.channel_data.list.iter() {
+        let name = channel.name.to_string();
+        let targets: &[usize] = match name.rsplit('.').next() {
+            Some("R") => &[0],
+            Some("G") => &[1],
+            Some("B") => &[2],
+            Some("A") => &[3],
+            Some("Y") => &[0, 1, 2],
+            _ => continue,
+        };
+        for (pixel, value) in pixels.iter_mut().zip(channel.sample_data.values_as_f32()) {
+            for &i in targets {
+                pixel[i] = value;
+            }
+        }
+    }
+    Ok(Decoded {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+        float: true,
+    })
+}
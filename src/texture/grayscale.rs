@@ -3,44 +3,87 @@ use std::path::Path;
 
 use serde::{de::Visitor, Deserialize, Deserializer};
 
-use super::Texture;
-use image::{self, GrayImage};
+use super::{
+    file, Channel, Input, MipMap, Node, Procedural, Sampler, TableDesc, TexCoord, Texture,
+};
 
+use crate::colorspace::ColorSpace;
 use crate::Vec2;
 use nalgebra_glm as glm;
 
 #[derive(Clone)]
 pub enum GrayScaleTexture {
-    Tex(GrayImage),
+    Tex(MipMap<f32>),
     Solid(f32),
+    Procedural(Procedural<f32>),
+    Node(Box<Node<GrayScaleTexture>>),
 }
 
-impl Texture for GrayScaleTexture {
-    type Pixel = f32;
-
-    fn dimensions(&self) -> Vec2 {
+impl GrayScaleTexture {
+    /// Size of a pixel in uv units, for textures that have pixels
+    pub fn texel_size(&self) -> Option<Vec2> {
         match self {
-            GrayScaleTexture::Tex(img) => glm::vec2(img.width() as f32, img.height() as f32),
-            GrayScaleTexture::Solid(_color) => glm::vec2(100.0, 100.0),
+            GrayScaleTexture::Tex(img) => {
+                Some(glm::vec2(1.0, 1.0).component_div(&img.dimensions()))
+            }
+            _ => None,
         }
     }
+}
+
+impl Default for GrayScaleTexture {
+    fn default() -> Self {
+        GrayScaleTexture::Solid(0.0)
+    }
+}
 
-    fn pixel_at(&self, x: u32, y: u32) -> Self::Pixel {
+impl Texture for GrayScaleTexture {
+    type Pixel = f32;
+
+    fn sample(&self, at: &TexCoord) -> Self::Pixel {
         match self {
-            GrayScaleTexture::Tex(img) => f32::from(img.get_pixel(x, y).0[0]) / 255.0,
+            GrayScaleTexture::Tex(img) => img.sample(at),
             GrayScaleTexture::Solid(color) => *color,
+            GrayScaleTexture::Procedural(p) => p.sample(at),
+            GrayScaleTexture::Node(node) => node.sample(at),
         }
     }
 }
 
-fn open<'a, P: AsRef<Path>>(path: P) -> Result<GrayScaleTexture, Box<dyn Error + 'a>> {
-    let img = image::open(path)?;
-    Ok(GrayScaleTexture::Tex(img.to_luma()))
+/// Reads one channel of an 8 or 16-bit integer image, or of a float Radiance HDR or OpenEXR file.
+/// Values are used as stored unless an sRGB colorspace is given.
+fn open<'a, P: AsRef<Path>>(
+    path: P,
+    channel: Channel,
+    space: Option<ColorSpace>,
+    sampler: Sampler,
+) -> Result<GrayScaleTexture, Box<dyn Error + 'a>> {
+    let file::Decoded {
+        width,
+        height,
+        pixels,
+        ..
+    } = file::read(path)?;
+    let space = space.unwrap_or(ColorSpace::Raw);
+    let buf = pixels
+        .into_iter()
+        .map(|[r, g, b, a]| channel.select([space.decode(r), space.decode(g), space.decode(b), a]))
+        .collect();
+    Ok(GrayScaleTexture::Tex(MipMap::new(
+        buf, width, height, sampler,
+    )))
+}
+
+/// Grayscale files are read as raw values unless they give a colorspace, so `space` is unused
+impl Input for GrayScaleTexture {
+    fn read(value: toml::Value, _space: Option<ColorSpace>) -> Result<Self, toml::de::Error> {
+        GrayScaleTexture::deserialize(value)
+    }
 }
 
 impl<'de> Deserialize<'de> for GrayScaleTexture {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        use serde::de::Error;
+        use serde::de::{Error, MapAccess};
         use std::fmt;
 
         struct TexVisitor;
@@ -49,12 +92,12 @@ impl<'de> Deserialize<'de> for GrayScaleTexture {
             type Value = GrayScaleTexture;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("path to grayscale image file or solid value")
+                formatter.write_str("path to grayscale image file, texture table or solid value")
             }
 
             // Load from texture file
             fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
-                open(value).map_err(E::custom)
+                open(value, Channel::default(), None, Sampler::default()).map_err(E::custom)
             }
 
             fn visit_u64<E: Error>(self, val: u64) -> Result<Self::Value, E> {
@@ -68,6 +111,18 @@ impl<'de> Deserialize<'de> for GrayScaleTexture {
             fn visit_f64<E: Error>(self, val: f64) -> Result<Self::Value, E> {
                 Ok(GrayScaleTexture::Solid(val as f32))
             }
+
+            // Texture file with sampling options, procedural pattern or node
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                match TableDesc::<f32, GrayScaleTexture>::from_map(map, None)? {
+                    TableDesc::Image(desc) => {
+                        open(&desc.path, desc.channel, desc.colorspace, desc.sampler)
+                            .map_err(A::Error::custom)
+                    }
+                    TableDesc::Procedural(p) => Ok(GrayScaleTexture::Procedural(p)),
+                    TableDesc::Node(node) => Ok(GrayScaleTexture::Node(Box::new(node))),
+                }
+            }
         }
 
         deserializer.deserialize_any(TexVisitor)
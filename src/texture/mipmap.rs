@@ -0,0 +1,226 @@
+use std::ops::*;
+
+use super::{Filter, Raster, Sampler, TexCoord};
+
+use crate::Vec2;
+use nalgebra_glm as glm;
+
+/// Image stored with successively halved copies, the first level being the original
+#[derive(Clone)]
+pub struct MipMap<P> {
+    levels: Vec<Level<P>>,
+}
+
+#[derive(Clone)]
+pub struct Level<P> {
+    buf: Vec<P>,
+    width: u32,
+    height: u32,
+    sampler: Sampler,
+}
+
+impl<P> MipMap<P>
+where
+    P: Copy + Mul<f32, Output = P> + Add<P, Output = P>,
+{
+    pub fn new(buf: Vec<P>, width: u32, height: u32, sampler: Sampler) -> Self {
+        let mut levels = vec![Level {
+            buf,
+            width,
+            height,
+            sampler,
+        }];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        MipMap { levels }
+    }
+
+    /// Applies `f` to every pixel of every level, for functions that commute with averaging
+    pub fn map<F: Fn(P) -> P>(&mut self, f: F) {
+        for level in &mut self.levels {
+            for p in &mut level.buf {
+                *p = f(*p);
+            }
+        }
+    }
+
+    /// Full resolution pixels
+    pub fn pixels(&self) -> &[P] {
+        &self.levels[0].buf
+    }
+
+    pub fn dimensions(&self) -> Vec2 {
+        self.levels[0].dimensions()
+    }
+
+    /// Filtered lookup, blending the two levels whose pixel size best matches the footprint
+    /// of `at`
+    pub fn sample(&self, at: &TexCoord) -> P {
+        let base = &self.levels[0];
+        let transform = &base.sampler.transform;
+        let dim = base.dimensions();
+        let dx = transform.linear(at.duvdx).component_mul(&dim);
+        let dy = transform.linear(at.duvdy).component_mul(&dim);
+        let width = f32::max(glm::length(&dx), glm::length(&dy));
+
+        let last = (self.levels.len() - 1) as f32;
+        let level = if width > 1.0 {
+            width.log2().min(last)
+        } else {
+            0.0
+        };
+        if base.sampler.filter == Filter::Nearest {
+            return self.levels[level.round() as usize].lookup(at.uv);
+        }
+        let lower = level.floor();
+        let t = level - lower;
+        let fine = self.levels[lower as usize].lookup(at.uv);
+        if t > 0.0 {
+            fine * (1.0 - t) + self.levels[lower as usize + 1].lookup(at.uv) * t
+        } else {
+            fine
+        }
+    }
+}
+
+impl<P> Level<P>
+where
+    P: Copy + Mul<f32, Output = P> + Add<P, Output = P>,
+{
+    /// Box filtered level of half the size, the last row and column of an odd sized level
+    /// being averaged into the last ones of the result
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut buf = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let xs = footprint(x, width, self.width);
+                let ys = footprint(y, height, self.height);
+                let count = xs.len() * ys.len();
+                let sum = ys
+                    .flat_map(|sy| xs.clone().map(move |sx| (sx, sy)))
+                    .map(|(sx, sy)| self.pixel_at(sx, sy))
+                    .reduce(|a, b| a + b)
+                    .unwrap();
+                buf.push(sum * (1.0 / count as f32));
+            }
+        }
+        Level {
+            buf,
+            width,
+            height,
+            sampler: self.sampler,
+        }
+    }
+}
+
+/// Rows or columns of a level of `size` averaged into `i` of the next one of `half`
+fn footprint(i: u32, half: u32, size: u32) -> Range<u32> {
+    let end = if i + 1 == half { size } else { 2 * i + 2 };
+    2 * i..end
+}
+
+impl<P> Raster for Level<P>
+where
+    P: Copy + Mul<f32, Output = P> + Add<P, Output = P>,
+{
+    type Pixel = P;
+
+    fn dimensions(&self) -> Vec2 {
+        glm::vec2(self.width as f32, self.height as f32)
+    }
+
+    fn pixel_at(&self, x: u32, y: u32) -> Self::Pixel {
+        self.buf[(y * self.width + x) as usize]
+    }
+
+    fn sampler(&self) -> Sampler {
+        self.sampler
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::Wrap;
+
+    fn level(buf: Vec<f32>, width: u32, height: u32) -> Level<f32> {
+        Level {
+            buf,
+            width,
+            height,
+            sampler: Sampler::default(),
+        }
+    }
+
+    #[test]
+    fn odd_edges_are_folded_in() {
+        let row = level(vec![0.0, 0.0, 0.0, 0.0, 6.0], 5, 1).downsample();
+        assert_eq!((row.width, row.height), (2, 1));
+        assert_eq!(row.buf, vec![0.0, 2.0]);
+
+        let square = level((0..9).map(|i| i as f32).collect(), 3, 3).downsample();
+        assert_eq!((square.width, square.height), (1, 1));
+        assert_eq!(square.buf, vec![4.0]);
+    }
+
+    #[test]
+    fn any_uv_gives_a_finite_value() {
+        let buf = (0..5 * 3).map(|i| i as f32).collect::<Vec<_>>();
+        let uvs = [
+            -1.3,
+            -0.0,
+            0.5,
+            1.0,
+            2.7,
+            1e9,
+            -1e9,
+            f32::INFINITY,
+            f32::NAN,
+        ];
+        for &wrap in &[Wrap::Repeat, Wrap::Clamp, Wrap::Mirror] {
+            for &filter in &[Filter::Nearest, Filter::Bilinear, Filter::Bicubic] {
+                let sampler = Sampler {
+                    wrap,
+                    filter,
+                    ..Sampler::default()
+                };
+                let mipmap = MipMap::new(buf.clone(), 5, 3, sampler);
+                for &u in &uvs {
+                    for &v in &uvs {
+                        let mut at = TexCoord::new(glm::vec2(u, v), glm::zero());
+                        at.duvdx = glm::vec2(0.3, 0.0);
+                        let value = mipmap.sample(&at);
+                        assert!(
+                            value.is_finite(),
+                            "{:?} {:?} at ({}, {}) gave {}",
+                            wrap,
+                            filter,
+                            u,
+                            v,
+                            value
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reaches_a_single_pixel() {
+        let mipmap = MipMap::new(vec![1.0; 7 * 3], 7, 3, Sampler::default());
+        let last = &mipmap.levels[mipmap.levels.len() - 1];
+        assert_eq!((last.width, last.height), (1, 1));
+        assert!(mipmap
+            .levels
+            .iter()
+            .all(|l| l.buf.iter().all(|&p| p == 1.0)));
+    }
+}
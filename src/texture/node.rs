@@ -0,0 +1,138 @@
+use std::ops::*;
+
+use serde::de::Error as _;
+use toml::value::{Table, Value};
+
+use super::{GrayScaleTexture, TexCoord, Texture};
+
+use crate::colorspace::ColorSpace;
+use crate::Vec3;
+
+/// Texture values the node operations can work on
+pub trait Channels: Copy + Mul<f32, Output = Self> + Add<Self, Output = Self> {
+    fn splat(value: f32) -> Self;
+
+    /// Component-wise product
+    fn modulate(self, other: Self) -> Self;
+}
+
+impl Channels for f32 {
+    fn splat(value: f32) -> Self {
+        value
+    }
+
+    fn modulate(self, other: Self) -> Self {
+        self * other
+    }
+}
+
+impl Channels for Vec3 {
+    fn splat(value: f32) -> Self {
+        Vec3::new(value, value, value)
+    }
+
+    fn modulate(self, other: Self) -> Self {
+        self.component_mul(&other)
+    }
+}
+
+/// Textures that can be the inputs of a node
+pub trait Input: Sized {
+    /// Reads the texture `value` describes, with files read as `space` unless they give one
+    fn read(value: Value, space: Option<ColorSpace>) -> Result<Self, toml::de::Error>;
+}
+
+/// Operation on other textures, written as a table named after it, e.g.
+/// `{ mix = ["wood.png", [0.2, 0.2, 0.2], "mask.png"] }`
+#[derive(Clone)]
+pub enum Node<T> {
+    /// Blends from the first input to the second as the mask goes from 0 to 1
+    Mix {
+        mix: (T, T, GrayScaleTexture),
+    },
+    Multiply {
+        multiply: (T, T),
+    },
+    /// Maps `from` linearly onto `to`, without clamping. `from` must not be empty.
+    Remap {
+        remap: T,
+        /// Defaults to 0 to 1
+        from: [f32; 2],
+        to: [f32; 2],
+    },
+    /// One minus the input
+    Invert {
+        invert: T,
+    },
+}
+
+impl<T: Input> Node<T> {
+    /// Node of the operation `table` is keyed by, with files of its inputs read as `space`
+    /// unless they give one
+    pub(super) fn from_table(
+        mut table: Table,
+        space: Option<ColorSpace>,
+    ) -> Result<Self, toml::de::Error> {
+        if let Some(inputs) = table.remove("mix") {
+            let (a, b, mask): (Value, Value, GrayScaleTexture) = inputs.try_into()?;
+            Ok(Node::Mix {
+                mix: (T::read(a, space)?, T::read(b, space)?, mask),
+            })
+        } else if let Some(inputs) = table.remove("multiply") {
+            let (a, b): (Value, Value) = inputs.try_into()?;
+            Ok(Node::Multiply {
+                multiply: (T::read(a, space)?, T::read(b, space)?),
+            })
+        } else if let Some(input) = table.remove("remap") {
+            let from = match table.remove("from") {
+                Some(from) => from.try_into()?,
+                None => [0.0, 1.0],
+            };
+            if from[0] == from[1] {
+                return Err(toml::de::Error::custom(format!(
+                    "remap `from` range must not be empty, got [{}, {}]",
+                    from[0], from[1]
+                )));
+            }
+            let to = table
+                .remove("to")
+                .ok_or_else(|| toml::de::Error::missing_field("to"))?;
+            Ok(Node::Remap {
+                remap: T::read(input, space)?,
+                from,
+                to: to.try_into()?,
+            })
+        } else if let Some(input) = table.remove("invert") {
+            Ok(Node::Invert {
+                invert: T::read(input, space)?,
+            })
+        } else {
+            Err(toml::de::Error::custom(
+                "expected a texture table with `path`, `pattern`, `mix`, `multiply`, `remap` \
+                 or `invert`",
+            ))
+        }
+    }
+}
+
+impl<T: Texture> Texture for Node<T>
+where
+    T::Pixel: Channels,
+{
+    type Pixel = T::Pixel;
+
+    fn sample(&self, at: &TexCoord) -> Self::Pixel {
+        match self {
+            Node::Mix { mix: (a, b, mask) } => {
+                let t = mask.sample(at);
+                a.sample(at) * (1.0 - t) + b.sample(at) * t
+            }
+            Node::Multiply { multiply: (a, b) } => a.sample(at).modulate(b.sample(at)),
+            Node::Remap { remap, from, to } => {
+                let scale = (to[1] - to[0]) / (from[1] - from[0]);
+                remap.sample(at) * scale + T::Pixel::splat(to[0] - from[0] * scale)
+            }
+            Node::Invert { invert } => T::Pixel::splat(1.0) + invert.sample(at) * -1.0,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::ops::*;
+
+use serde::Deserialize;
+
+use super::{TexCoord, Texture};
+
+use crate::Vec3;
+use nalgebra_glm as glm;
+
+/// Coordinates a procedural texture is evaluated in
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Space {
+    #[default]
+    Uv,
+    World,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "pattern", rename_all = "lowercase")]
+pub enum Pattern {
+    /// Alternating unit cells
+    Checker,
+    /// Linear ramp from 0 to 1 along `direction`
+    Gradient {
+        #[serde(default = "default_direction")]
+        direction: Vec3,
+    },
+    /// Fractal sum of Perlin noise
+    Noise {
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f32,
+        #[serde(default = "default_gain")]
+        gain: f32,
+    },
+    /// Distance to the nearest of randomly placed points, one per unit cell
+    Voronoi,
+}
+
+fn default_direction() -> Vec3 {
+    glm::vec3(1.0, 0.0, 0.0)
+}
+
+fn default_octaves() -> u32 {
+    4
+}
+
+fn default_lacunarity() -> f32 {
+    2.0
+}
+
+fn default_gain() -> f32 {
+    0.5
+}
+
+impl Pattern {
+    /// Value of the pattern at `p`, between 0 and 1
+    pub fn eval(&self, p: &Vec3) -> f32 {
+        match self {
+            Pattern::Checker => {
+                // Nudged so that surfaces lying exactly on a cell border don't flicker
+                let cell = glm::floor(&(p + glm::vec3(1e-3, 1e-3, 1e-3)));
+                (cell.x + cell.y + cell.z).rem_euclid(2.0)
+            }
+            Pattern::Gradient { direction } => glm::dot(p, direction).clamp(0.0, 1.0),
+            Pattern::Noise {
+                octaves,
+                lacunarity,
+                gain,
+            } => {
+                let (mut sum, mut total) = (0.0, 0.0);
+                let (mut frequency, mut amplitude) = (1.0, 1.0);
+                for _ in 0..*octaves {
+                    sum += amplitude * perlin(&(p * frequency));
+                    total += amplitude;
+                    frequency *= lacunarity;
+                    amplitude *= gain;
+                }
+                let noise = if total > 0.0 { sum / total } else { 0.0 };
+                (0.5 + 0.5 * noise).clamp(0.0, 1.0)
+            }
+            Pattern::Voronoi => {
+                let cell = glm::floor(p);
+                let mut nearest = f32::MAX;
+                for x in -1..=1 {
+                    for y in -1..=1 {
+                        for z in -1..=1 {
+                            let neighbour = cell + glm::vec3(x as f32, y as f32, z as f32);
+                            let feature = neighbour + jitter(&neighbour);
+                            nearest = nearest.min(glm::distance(p, &feature));
+                        }
+                    }
+                }
+                nearest.min(1.0)
+            }
+        }
+    }
+}
+
+/// Pattern blending between two values, e.g.
+/// `{ pattern = "checker", scale = 8, colors = [[1, 1, 1], [0, 0, 0]] }`
+#[derive(Deserialize, Clone)]
+pub struct Procedural<T> {
+    #[serde(flatten)]
+    pub pattern: Pattern,
+    #[serde(default)]
+    pub space: Space,
+    /// Pattern cells per unit of the evaluation space
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Values at 0 and 1 of the pattern, `colors` for color textures
+    #[serde(alias = "colors")]
+    pub values: [T; 2],
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl<T> Texture for Procedural<T>
+where
+    T: Copy + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    type Pixel = T;
+
+    fn sample(&self, at: &TexCoord) -> Self::Pixel {
+        let p = match self.space {
+            Space::Uv => glm::vec3(at.uv.x, at.uv.y, 0.0),
+            Space::World => at.point,
+        };
+        let t = self.pattern.eval(&(p * self.scale));
+        self.values[0] * (1.0 - t) + self.values[1] * t
+    }
+}
+
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^ (h >> 15)
+}
+
+/// Integer lattice coordinate of `c`, wrapping around instead of saturating far from the
+/// origin
+fn lattice(c: f32) -> i32 {
+    c as i64 as i32
+}
+
+/// Random offset within the cell at `cell`
+fn jitter(cell: &Vec3) -> Vec3 {
+    let (x, y, z) = (lattice(cell.x), lattice(cell.y), lattice(cell.z));
+    let unit = |h: u32| h as f32 / u32::MAX as f32;
+    glm::vec3(
+        unit(hash(x, y, z)),
+        unit(hash(x ^ 0x55, y, z)),
+        unit(hash(x, y ^ 0x55, z)),
+    )
+}
+
+/// Dot product of `d` with one of the cube's edge directions, picked by `h`
+fn gradient(h: u32, d: &Vec3) -> f32 {
+    match h % 12 {
+        0 => d.x + d.y,
+        1 => -d.x + d.y,
+        2 => d.x - d.y,
+        3 => -d.x - d.y,
+        4 => d.x + d.z,
+        5 => -d.x + d.z,
+        6 => d.x - d.z,
+        7 => -d.x - d.z,
+        8 => d.y + d.z,
+        9 => -d.y + d.z,
+        10 => d.y - d.z,
+        _ => -d.y - d.z,
+    }
+}
+
+/// Improved Perlin noise, roughly between -1 and 1
+fn perlin(p: &Vec3) -> f32 {
+    let cell = glm::floor(p);
+    let f = p - cell;
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+    let (x, y, z) = (lattice(cell.x), lattice(cell.y), lattice(cell.z));
+    let corner = |i: i32, j: i32, k: i32| {
+        let offset = glm::vec3(i as f32, j as f32, k as f32);
+        gradient(
+            hash(x.wrapping_add(i), y.wrapping_add(j), z.wrapping_add(k)),
+            &(f - offset),
+        )
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+    lerp(lerp(x00, x10, v), lerp(x01, x11, v), w)
+}
@@ -27,3 +27,26 @@ pub fn component_minmax((mut min, mut max): (Vec3, Vec3), a: &Vec3) -> (Vec3, Ve
     }
     (min, max)
 }
+
+/// Arbitrary unit vector perpendicular to `norm`
+pub fn perpendicular(norm: &Vec3) -> Vec3 {
+    // Find an axis that is not parallel to normal
+    let major_axis = if f32::abs(norm.x) < (1.0 / f32::sqrt(3.0)) {
+        glm::vec3(1.0, 0.0, 0.0)
+    } else if f32::abs(norm.y) < (1.0 / f32::sqrt(3.0)) {
+        glm::vec3(0.0, 1.0, 0.0)
+    } else {
+        glm::vec3(0.0, 0.0, 1.0)
+    };
+    glm::normalize(&norm.cross(&major_axis))
+}
+
+pub fn transform_to_world(vec: &Vec3, norm: &Vec3) -> Vec3 {
+    // Create a coordinate system relative to world space
+    let u = perpendicular(norm);
+    let v = norm.cross(&u);
+    let w = norm;
+
+    // Transform from local coordinates to world coordinates
+    v * vec.x + w * vec.y + u * vec.z
+}